@@ -1,137 +1,633 @@
-use crate::{Point2, Vector2};
+use crate::camera::Camera;
+use crate::flight::FlightModel;
+use crate::{collision, Point2, Vector2};
 use ggez::{graphics, nalgebra as na, Context, GameResult};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64Mcg;
 
-const PLAYER_LIFE: f32 = 1.0;
-const SHOT_LIFE: f32 = 2.0;
-const ROCK_LIFE: f32 = 1.0;
+const ROCK_HEALTH: f32 = 1.0;
+// Shots and pickups don't get worn down, they just run out of time.
+const SHOT_HEALTH: f32 = 1.0;
+const PICKUP_HEALTH: f32 = 1.0;
+
+// Seconds before shots and pickups disappear.
+const SHOT_LIFETIME: f32 = 2.0;
+const PICKUP_LIFETIME: f32 = 8.0;
+
+// Seconds something glows after it gets hit.
+const FLASH_TIME: f32 = 0.15;
+
+const SHOT_DAMAGE: f32 = 1.0;
 
-const PLAYER_BBOX: f32 = 12.0;
 const ROCK_BBOX: f32 = 12.0;
+const SAUCER_BBOX: f32 = 12.0;
+const PICKUP_BBOX: f32 = 8.0;
 const SHOT_BBOX: f32 = 6.0;
 
-const SHOT_ANG_VEL: f32 = 0.1;
+// The radius of a medium rock's outline before it's roughed up.
+const ROCK_RADIUS: f32 = 10.0;
+
+// Spin in radians per second.
+const SHOT_ANG_VEL: f32 = 6.0;
+const PICKUP_ANG_VEL: f32 = 2.0;
 
 // An Actor is anything in the game world. We're not *quite* making a real entity-component system
 // but it's pretty close. For a more complicated game you would want a real ECS, but for this it's
 // enough to say that all our game objects contain pretty much the same data.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ActorType {
-    Player,
-    Rock,
+    Player(ShipClass),
+    Rock(RockSize),
+    Saucer(SaucerSize),
     Shot,
+    Pickup(PickupKind),
+}
+
+/// The kinds of ship there are to fly. Each has its own hull, handling, toughness and gun.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ShipClass {
+    #[default]
+    Balanced,
+    Scout,
+    Gunship,
+}
+
+impl ShipClass {
+    pub const ALL: [ShipClass; 3] = [ShipClass::Balanced, ShipClass::Scout, ShipClass::Gunship];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ShipClass::Balanced => "balanced",
+            ShipClass::Scout => "scout",
+            ShipClass::Gunship => "gunship",
+        }
+    }
+
+    /// The shape of the hull, pointing up.
+    pub fn outline(self) -> Vec<na::Point2<f32>> {
+        let points: &[(f32, f32)] = match self {
+            ShipClass::Balanced => &[(0.0, -10.0), (8.0, 10.0), (0.0, 8.0), (-8.0, 10.0)],
+            ShipClass::Scout => &[(0.0, -12.0), (5.0, 8.0), (0.0, 5.0), (-5.0, 8.0)],
+            ShipClass::Gunship => &[
+                (0.0, -10.0),
+                (6.0, -4.0),
+                (12.0, 8.0),
+                (4.0, 6.0),
+                (-4.0, 6.0),
+                (-12.0, 8.0),
+                (-6.0, -4.0),
+            ],
+        };
+        points.iter().map(|&(x, y)| na::Point2::new(x, y)).collect()
+    }
+
+    pub fn bbox_size(self) -> f32 {
+        match self {
+            ShipClass::Balanced => 12.0,
+            ShipClass::Scout => 9.0,
+            ShipClass::Gunship => 15.0,
+        }
+    }
+
+    pub fn health(self) -> f32 {
+        match self {
+            ShipClass::Balanced => 3.0,
+            ShipClass::Scout => 2.0,
+            ShipClass::Gunship => 4.0,
+        }
+    }
+
+    /// How the ship handles out of the box, before any tuning from a config file.
+    pub fn flight(self) -> FlightModel {
+        match self {
+            ShipClass::Balanced => FlightModel::default(),
+            ShipClass::Scout => FlightModel {
+                thrust: 150.0,
+                reverse_thrust: 70.0,
+                strafe_thrust: 80.0,
+                turn_rate: 4.0,
+                turn_accel: 40.0,
+                drag: 0.0,
+                max_speed: 320.0,
+            },
+            ShipClass::Gunship => FlightModel {
+                thrust: 70.0,
+                reverse_thrust: 40.0,
+                strafe_thrust: 30.0,
+                turn_rate: 2.2,
+                turn_accel: 15.0,
+                drag: 0.0,
+                max_speed: 200.0,
+            },
+        }
+    }
+
+    /// The index into `weapon::WEAPONS` of the gun the ship starts out with.
+    pub fn weapon(self) -> usize {
+        match self {
+            ShipClass::Balanced => 0,
+            ShipClass::Scout => 1,
+            ShipClass::Gunship => 2,
+        }
+    }
+
+    pub fn to_f64(self) -> f64 {
+        match self {
+            ShipClass::Balanced => 0.0,
+            ShipClass::Scout => 1.0,
+            ShipClass::Gunship => 2.0,
+        }
+    }
+
+    pub fn from_f64(value: f64) -> Option<ShipClass> {
+        match value as i32 {
+            0 => Some(ShipClass::Balanced),
+            1 => Some(ShipClass::Scout),
+            2 => Some(ShipClass::Gunship),
+            _ => None,
+        }
+    }
+}
+
+/// Rocks come in three sizes. Shooting a rock breaks it up into a few smaller, faster ones, and
+/// the small ones break up into nothing at all.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RockSize {
+    Large,
+    Medium,
+    Small,
+}
+
+impl RockSize {
+    /// How big this size of rock is compared to the basic rock outline.
+    pub fn scale(self) -> f32 {
+        match self {
+            RockSize::Large => 2.0,
+            RockSize::Medium => 1.0,
+            RockSize::Small => 0.5,
+        }
+    }
+
+    /// How much faster than a large rock this size of rock moves.
+    pub fn speed(self) -> f32 {
+        match self {
+            RockSize::Large => 1.0,
+            RockSize::Medium => 1.5,
+            RockSize::Small => 2.0,
+        }
+    }
+
+    /// What shooting this size of rock is worth. Smaller rocks are harder to hit.
+    pub fn points(self) -> i32 {
+        match self {
+            RockSize::Large => 20,
+            RockSize::Medium => 50,
+            RockSize::Small => 100,
+        }
+    }
+
+    /// How much damage this size of rock does to a ship that flies into it.
+    pub fn damage(self) -> f32 {
+        match self {
+            RockSize::Large => 3.0,
+            RockSize::Medium => 2.0,
+            RockSize::Small => 1.0,
+        }
+    }
+
+    /// The size of the pieces this size of rock breaks up into, if it breaks up into anything.
+    pub fn smaller(self) -> Option<RockSize> {
+        match self {
+            RockSize::Large => Some(RockSize::Medium),
+            RockSize::Medium => Some(RockSize::Small),
+            RockSize::Small => None,
+        }
+    }
+
+    pub fn to_f64(self) -> f64 {
+        match self {
+            RockSize::Large => 0.0,
+            RockSize::Medium => 1.0,
+            RockSize::Small => 2.0,
+        }
+    }
+
+    pub fn from_f64(value: f64) -> Option<RockSize> {
+        match value as i32 {
+            0 => Some(RockSize::Large),
+            1 => Some(RockSize::Medium),
+            2 => Some(RockSize::Small),
+            _ => None,
+        }
+    }
+}
+
+/// Saucers come in two sizes. Big ones are easy to hit and shoot wildly; small ones are worth a lot
+/// more and aim at you.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SaucerSize {
+    Large,
+    Small,
+}
+
+impl SaucerSize {
+    pub fn scale(self) -> f32 {
+        match self {
+            SaucerSize::Large => 1.0,
+            SaucerSize::Small => 0.6,
+        }
+    }
+
+    pub fn health(self) -> f32 {
+        match self {
+            SaucerSize::Large => 2.0,
+            SaucerSize::Small => 1.0,
+        }
+    }
+
+    pub fn points(self) -> i32 {
+        match self {
+            SaucerSize::Large => 200,
+            SaucerSize::Small => 1000,
+        }
+    }
+
+    pub fn to_f64(self) -> f64 {
+        match self {
+            SaucerSize::Large => 0.0,
+            SaucerSize::Small => 1.0,
+        }
+    }
+
+    pub fn from_f64(value: f64) -> Option<SaucerSize> {
+        match value as i32 {
+            0 => Some(SaucerSize::Large),
+            1 => Some(SaucerSize::Small),
+            _ => None,
+        }
+    }
+}
+
+/// The power-ups that destroyed rocks sometimes leave behind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PickupKind {
+    RapidFire,
+    Spread,
+    Piercing,
+    ExtraLife,
+    Shield,
+}
+
+impl PickupKind {
+    pub const ALL: [PickupKind; 5] = [
+        PickupKind::RapidFire,
+        PickupKind::Spread,
+        PickupKind::Piercing,
+        PickupKind::ExtraLife,
+        PickupKind::Shield,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PickupKind::RapidFire => "Rapid fire",
+            PickupKind::Spread => "Spread shot",
+            PickupKind::Piercing => "Piercing shot",
+            PickupKind::ExtraLife => "Extra life",
+            PickupKind::Shield => "Shield",
+        }
+    }
+}
+
+/// Makes a jagged outline for a rock by walking around a circle, nudging each point a little
+/// forwards or backwards and pulling it in towards the middle by a random amount. The points stay
+/// in order around the origin, so the outline can dent inwards but never folds over itself. It's
+/// seeded from the rock's id, so the same rock looks the same to everyone. The id is shifted so
+/// the dice come out differently from the ones used to split it, or its shape would give away how
+/// many pieces it breaks into.
+fn rock_outline(id: u32, size: RockSize) -> Vec<na::Point2<f32>> {
+    let mut rng = Pcg64Mcg::seed_from_u64(u64::from(id) << 16);
+    let points = rng.gen_range(8, 13);
+    let step = 2.0 * std::f32::consts::PI / points as f32;
+
+    (0..points)
+        .map(|i| {
+            let angle = (i as f32 + rng.gen_range(-0.3, 0.3)) * step;
+            let radius = ROCK_RADIUS * size.scale() * rng.gen_range(0.6, 1.0);
+            na::Point2::new(angle.sin() * radius, -angle.cos() * radius)
+        })
+        .collect()
 }
 
 #[derive(Debug)]
 pub struct Actor {
     pub tag: ActorType,
+    // Identifies the actor across peers. Rocks, saucers and pickups use it.
+    pub id: u32,
     pub pos: Point2,
     pub facing: f32,
     pub velocity: Vector2,
+    // Radians per second.
     pub ang_vel: f32,
     pub bbox_size: f32,
     pub owner: String,
-
-    // Lazily overload "life" with a double meaning: for shots, it is the time left to live, for
-    // players and rocks, it is the actual hit points.
-    pub life: f32,
+    // The shape of the actor, drawn in screen space around its origin.
+    pub outline: Vec<na::Point2<f32>>,
+    // Seconds left during which rocks pass straight through it. Ships get this when they respawn.
+    pub invulnerable: f32,
+    // Seconds left before a ship can be hurt again after taking a hit.
+    pub hit_grace: f32,
+    // Seconds left on the ship's shield, and until the shield and hyperspace can be used again.
+    pub shield: f32,
+    pub shield_cooldown: f32,
+    pub hyperspace_cooldown: f32,
+    // How many more things a shot can go through before it's used up.
+    pub pierce: u32,
+    // The ids of the things a shot has already gone through, so it doesn't hit them again.
+    pub hits: Vec<u32>,
+    // How much health a shot knocks off whatever it hits.
+    pub damage: f32,
+    // Which of the `weapon::WEAPONS` a ship has picked.
+    pub weapon: usize,
+    // Hit points. Anything that runs out of them is destroyed.
+    pub health: f32,
+    // Seconds left to live, for things that only last a while. Everything else lives forever.
+    pub lifetime: f32,
+    // Seconds left of the glow from the last time it was hit.
+    pub flash: f32,
 }
 
 impl Actor {
-    pub fn polygon(&self) -> Vec<na::Point2<f32>> {
-        match self.tag {
-            ActorType::Player => vec![
-                na::Point2::new(0.0, -10.0),
-                na::Point2::new(8.0, 10.0),
-                na::Point2::new(0.0, 8.0),
-                na::Point2::new(-8.0, 10.0),
-            ],
-            ActorType::Rock => vec![
-                na::Point2::new(0.0, -10.0),
-                na::Point2::new(8.0, -2.0),
-                na::Point2::new(5.0, 10.0),
-                na::Point2::new(-5.0, 10.0),
-                na::Point2::new(-8.0, -2.0),
-            ],
-            ActorType::Shot => vec![
-                na::Point2::new(0.0, -5.0),
-                na::Point2::new(4.0, -1.0),
-                na::Point2::new(2.5, 5.0),
-                na::Point2::new(-2.5, 5.0),
-                na::Point2::new(-4.0, -1.0),
-            ],
+    pub fn polygon(&self) -> &[na::Point2<f32>] {
+        &self.outline
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.health > 0.0 && self.lifetime > 0.0
+    }
+
+    /// Knocks some health off and makes it flash so everyone can see it was hit.
+    pub fn take_damage(&mut self, damage: f32) {
+        self.health -= damage;
+        self.flash = FLASH_TIME;
+    }
+
+    pub fn create_player(owner: String, class: ShipClass) -> Self {
+        Self {
+            tag: ActorType::Player(class),
+            id: 0,
+            pos: Point2::origin(),
+            facing: 0.,
+            velocity: na::zero(),
+            ang_vel: 0.,
+            bbox_size: class.bbox_size(),
+            health: class.health(),
+            lifetime: f32::INFINITY,
+            flash: 0.,
+            owner,
+            invulnerable: 0.,
+            hit_grace: 0.,
+            shield: 0.,
+            shield_cooldown: 0.,
+            hyperspace_cooldown: 0.,
+            pierce: 0,
+            hits: Vec::new(),
+            damage: SHOT_DAMAGE,
+            weapon: class.weapon(),
+            outline: class.outline(),
         }
     }
 
-    pub fn create_player(owner: String) -> Self {
+    /// Creates a rock. Its outline is generated from its id.
+    pub fn create_rock(owner: String, size: RockSize, id: u32) -> Self {
         Self {
-            tag: ActorType::Player,
+            tag: ActorType::Rock(size),
+            id,
             pos: Point2::origin(),
             facing: 0.,
             velocity: na::zero(),
             ang_vel: 0.,
-            bbox_size: PLAYER_BBOX,
-            life: PLAYER_LIFE,
+            bbox_size: ROCK_BBOX * size.scale(),
+            health: ROCK_HEALTH,
+            lifetime: f32::INFINITY,
+            flash: 0.,
             owner,
+            invulnerable: 0.,
+            hit_grace: 0.,
+            shield: 0.,
+            shield_cooldown: 0.,
+            hyperspace_cooldown: 0.,
+            pierce: 0,
+            hits: Vec::new(),
+            damage: SHOT_DAMAGE,
+            weapon: 0,
+            outline: rock_outline(id, size),
         }
     }
 
-    pub fn create_rock(owner: String) -> Self {
+    pub fn create_saucer(owner: String, size: SaucerSize, id: u32) -> Self {
+        let scale = size.scale();
         Self {
-            tag: ActorType::Rock,
+            tag: ActorType::Saucer(size),
+            id,
             pos: Point2::origin(),
             facing: 0.,
             velocity: na::zero(),
             ang_vel: 0.,
-            bbox_size: ROCK_BBOX,
-            life: ROCK_LIFE,
+            bbox_size: SAUCER_BBOX * scale,
+            health: size.health(),
+            lifetime: f32::INFINITY,
+            flash: 0.,
             owner,
+            invulnerable: 0.,
+            hit_grace: 0.,
+            shield: 0.,
+            shield_cooldown: 0.,
+            hyperspace_cooldown: 0.,
+            pierce: 0,
+            hits: Vec::new(),
+            damage: SHOT_DAMAGE,
+            weapon: 0,
+            outline: [
+                (-12.0, 0.0),
+                (-5.0, -4.0),
+                (-3.0, -8.0),
+                (3.0, -8.0),
+                (5.0, -4.0),
+                (12.0, 0.0),
+                (6.0, 5.0),
+                (-6.0, 5.0),
+            ]
+            .iter()
+            .map(|&(x, y)| na::Point2::new(x * scale, y * scale))
+            .collect(),
+        }
+    }
+
+    pub fn create_pickup(owner: String, kind: PickupKind, id: u32) -> Self {
+        Self {
+            tag: ActorType::Pickup(kind),
+            id,
+            pos: Point2::origin(),
+            facing: 0.,
+            velocity: na::zero(),
+            ang_vel: PICKUP_ANG_VEL,
+            bbox_size: PICKUP_BBOX,
+            health: PICKUP_HEALTH,
+            lifetime: PICKUP_LIFETIME,
+            flash: 0.,
+            owner,
+            invulnerable: 0.,
+            hit_grace: 0.,
+            shield: 0.,
+            shield_cooldown: 0.,
+            hyperspace_cooldown: 0.,
+            pierce: 0,
+            hits: Vec::new(),
+            damage: SHOT_DAMAGE,
+            weapon: 0,
+            outline: vec![
+                na::Point2::new(0.0, -7.0),
+                na::Point2::new(6.0, 0.0),
+                na::Point2::new(0.0, 7.0),
+                na::Point2::new(-6.0, 0.0),
+            ],
         }
     }
 
     pub fn create_shot(owner: String) -> Self {
         Self {
             tag: ActorType::Shot,
+            id: 0,
             pos: Point2::origin(),
             facing: 0.,
             velocity: na::zero(),
             ang_vel: SHOT_ANG_VEL,
             bbox_size: SHOT_BBOX,
-            life: SHOT_LIFE,
+            health: SHOT_HEALTH,
+            lifetime: SHOT_LIFETIME,
+            flash: 0.,
             owner,
+            invulnerable: 0.,
+            hit_grace: 0.,
+            shield: 0.,
+            shield_cooldown: 0.,
+            hyperspace_cooldown: 0.,
+            pierce: 0,
+            hits: Vec::new(),
+            damage: SHOT_DAMAGE,
+            weapon: 0,
+            outline: vec![
+                na::Point2::new(0.0, -5.0),
+                na::Point2::new(4.0, -1.0),
+                na::Point2::new(2.5, 5.0),
+                na::Point2::new(-2.5, 5.0),
+                na::Point2::new(-4.0, -1.0),
+            ],
         }
     }
 
+    /// Draws the actor as seen by `camera`. The world wraps around, so when the whole world is on
+    /// screen an actor hanging over an edge is drawn a second time poking in from the opposite edge
+    /// (and up to four times in a corner). Anything out of sight isn't drawn at all.
     pub fn draw_actor(
         &self,
         ctx: &mut Context,
-        world_coords: (f32, f32),
-        hidpi_factor: f32,
+        camera: &Camera,
         color: graphics::Color,
     ) -> GameResult {
-        let (screen_w, screen_h) = world_coords;
-        let pos = Self::world_to_screen_coords(screen_w, screen_h, self.pos);
-        let drawparams = graphics::DrawParam::new()
-            .dest(pos)
-            .rotation(self.facing as f32)
-            .scale(Vector2::new(hidpi_factor, hidpi_factor))
-            .offset(Point2::new(0.5, 0.5));
+        // Leave room for the shield, which sits outside the hull.
+        let radius = collision::bounding_radius(self) + 4.0;
+        let seam_offsets = |pos: f32, view: f32, world: f32| {
+            if pos + radius > view {
+                vec![0.0, -world]
+            } else if pos - radius < 0.0 {
+                vec![0.0, world]
+            } else {
+                vec![0.0]
+            }
+        };
+        let on_screen = |pos: f32, view: f32| pos + radius >= 0.0 && pos - radius <= view;
+
+        let center = Self::world_to_screen_coords(camera, self.pos);
+        let (view_w, view_h) = (camera.view_width, camera.view_height);
+        let mut positions = Vec::new();
+        for dx in seam_offsets(center.x, view_w, camera.world_width) {
+            for dy in seam_offsets(center.y, view_h, camera.world_height) {
+                let pos = center + Vector2::new(dx, dy);
+                if on_screen(pos.x, view_w) && on_screen(pos.y, view_h) {
+                    positions.push(pos);
+                }
+            }
+        }
+        if positions.is_empty() {
+            return Ok(());
+        }
+
+        let color = if self.flash > 0.0 {
+            graphics::Color::new(1.0, 0.3, 0.3, 1.0)
+        } else {
+            color
+        };
 
         let mesh = graphics::Mesh::new_polygon(
             ctx,
             graphics::DrawMode::stroke(1.0),
-            &self.polygon(),
+            self.polygon(),
             color,
         )
         .expect("could not create polygon");
-        graphics::draw(ctx, &mesh, drawparams)
+        let shield = if self.shield > 0.0 {
+            let radius = collision::bounding_radius(self) + 4.0;
+            let mesh = graphics::Mesh::new_circle(
+                ctx,
+                graphics::DrawMode::stroke(1.0),
+                na::Point2::origin(),
+                radius,
+                0.5,
+                graphics::Color::new(0.3, 0.7, 1.0, 1.0),
+            )?;
+            Some(mesh)
+        } else {
+            None
+        };
+
+        for pos in positions {
+            let drawparams = graphics::DrawParam::new()
+                .dest(pos)
+                .rotation(self.facing)
+                .offset(Point2::new(0.5, 0.5));
+
+            graphics::draw(ctx, &mesh, drawparams)?;
+            if let Some(shield) = &shield {
+                graphics::draw(ctx, shield, drawparams)?;
+            }
+        }
+
+        Ok(())
     }
 
     /// Translates the world coordinate system, which has Y pointing up and the origin at the
     /// center, to the screen coordinate system, which has Y pointing downward and the origin at
-    /// the top-left.
-    fn world_to_screen_coords(screen_width: f32, screen_height: f32, point: Point2) -> Point2 {
-        let x = point.x + screen_width / 2.0;
-        let y = screen_height - (point.y + screen_height / 2.0);
+    /// the top-left, with the camera's position in the middle of the screen.
+    pub(crate) fn world_to_screen_coords(camera: &Camera, point: Point2) -> Point2 {
+        let offset = camera.offset(point);
+        let x = offset.x + camera.view_width / 2.0;
+        let y = camera.view_height - (offset.y + camera.view_height / 2.0);
         Point2::new(x, y)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rock_outlines() {
+        let a = Actor::create_rock("self".to_string(), RockSize::Large, 7);
+        let b = Actor::create_rock("self".to_string(), RockSize::Large, 7);
+        let c = Actor::create_rock("self".to_string(), RockSize::Large, 8);
+
+        assert_eq!(a.polygon(), b.polygon());
+        assert_ne!(a.polygon(), c.polygon());
+    }
+}
@@ -0,0 +1,257 @@
+use crate::collision::wrapped_delta;
+use crate::{Point2, Vector2};
+
+// The distance at which a star pulls with its full `gravity`.
+const GRAVITY_REFERENCE_DISTANCE: f32 = 100.0;
+
+/// A star that pulls everything towards it and burns up whatever flies into it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Star {
+    pub pos: Point2,
+    pub radius: f32,
+    /// How hard the star pulls, as the acceleration in pixels per second squared felt 100 pixels
+    /// from its centre. The pull falls off with the square of the distance.
+    pub gravity: f32,
+}
+
+/// A solid lump that things bounce off and shots break against.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Obstacle {
+    pub pos: Point2,
+    pub radius: f32,
+}
+
+/// A pair of holes: anything that flies into one comes out of the other, still moving the same
+/// way.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Wormhole {
+    pub a: Point2,
+    pub b: Point2,
+    pub radius: f32,
+}
+
+/// The fixed features of the playfield, read from the file given with `--arena`. The default is
+/// the classic empty field. Everyone in a game needs the same arena, since each peer moves the
+/// shared rocks itself.
+///
+/// The world wraps at its edges, so the checks all take its size and measure the short way round,
+/// which might be across a seam.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Arena {
+    pub stars: Vec<Star>,
+    pub obstacles: Vec<Obstacle>,
+    pub wormholes: Vec<Wormhole>,
+}
+
+impl Arena {
+    /// Reads an arena from lines of the form:
+    ///
+    /// ```text
+    /// star X Y RADIUS GRAVITY
+    /// obstacle X Y RADIUS
+    /// wormhole X1 Y1 X2 Y2 RADIUS
+    /// ```
+    ///
+    /// Blank lines and lines starting with `#` are skipped.
+    pub fn parse(text: &str) -> Result<Arena, String> {
+        let mut arena = Arena::default();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let kind = words.next().unwrap_or("");
+            let values = words
+                .map(|w| {
+                    w.parse::<f32>()
+                        .map_err(|_| format!("line {}: invalid number: {}", number + 1, w))
+                })
+                .collect::<Result<Vec<f32>, String>>()?;
+
+            let expected = match kind {
+                "star" => 4,
+                "obstacle" => 3,
+                "wormhole" => 5,
+                _ => return Err(format!("line {}: unknown feature: {}", number + 1, kind)),
+            };
+            if values.len() != expected {
+                return Err(format!(
+                    "line {}: {} takes {} numbers",
+                    number + 1,
+                    kind,
+                    expected
+                ));
+            }
+
+            let v = &values;
+            match kind {
+                "star" => arena.stars.push(Star {
+                    pos: Point2::new(v[0], v[1]),
+                    radius: v[2],
+                    gravity: v[3],
+                }),
+                "obstacle" => arena.obstacles.push(Obstacle {
+                    pos: Point2::new(v[0], v[1]),
+                    radius: v[2],
+                }),
+                _ => arena.wormholes.push(Wormhole {
+                    a: Point2::new(v[0], v[1]),
+                    b: Point2::new(v[2], v[3]),
+                    radius: v[4],
+                }),
+            }
+        }
+
+        Ok(arena)
+    }
+
+    /// The pull of all the stars on something at `pos`. It stops growing at a star's surface, so
+    /// nothing gets flung off at silly speeds by passing close to the centre.
+    pub fn gravity(&self, pos: Point2, width: f32, height: f32) -> Vector2 {
+        self.stars
+            .iter()
+            .map(|star| {
+                let delta = wrapped_delta(pos, star.pos, width, height);
+                let distance = delta.norm().max(star.radius).max(1.0);
+                let strength = star.gravity * (GRAVITY_REFERENCE_DISTANCE / distance).powi(2);
+                delta / distance * strength
+            })
+            .fold(Vector2::new(0.0, 0.0), |total, pull| total + pull)
+    }
+
+    /// Where something at `pos` moving at `velocity` comes out, if it's fallen into a wormhole.
+    /// It's put just past the far end's rim in the direction it's moving, so it doesn't fall
+    /// straight back in. Something standing still stays put.
+    pub fn wormhole_exit(
+        &self,
+        pos: Point2,
+        velocity: Vector2,
+        width: f32,
+        height: f32,
+    ) -> Option<Point2> {
+        let speed = velocity.norm();
+        if speed == 0.0 {
+            return None;
+        }
+
+        let distance = |to: Point2| wrapped_delta(pos, to, width, height).norm();
+        self.wormholes.iter().find_map(|hole| {
+            let exit = if distance(hole.a) < hole.radius {
+                hole.b
+            } else if distance(hole.b) < hole.radius {
+                hole.a
+            } else {
+                return None;
+            };
+            Some(exit + velocity / speed * (hole.radius + 1.0))
+        })
+    }
+
+    /// The star that a circle at `pos` of `radius` is touching, if any.
+    pub fn star_at(&self, pos: Point2, radius: f32, width: f32, height: f32) -> Option<&Star> {
+        self.stars.iter().find(|star| {
+            wrapped_delta(pos, star.pos, width, height).norm() < star.radius + radius
+        })
+    }
+
+    /// If a circle at `pos` of `radius` overlaps an obstacle, the direction to push it out in and
+    /// how far it has to go.
+    pub fn obstacle_push(
+        &self,
+        pos: Point2,
+        radius: f32,
+        width: f32,
+        height: f32,
+    ) -> Option<(Vector2, f32)> {
+        self.obstacles.iter().find_map(|obstacle| {
+            let delta = wrapped_delta(obstacle.pos, pos, width, height);
+            let distance = delta.norm();
+            let depth = obstacle.radius + radius - distance;
+            if depth <= 0.0 {
+                return None;
+            }
+            let normal = if distance > 0.0 {
+                delta / distance
+            } else {
+                Vector2::new(0.0, 1.0)
+            };
+            Some((normal, depth))
+        })
+    }
+
+    /// How far `pos` is from the edge of the nearest star or obstacle.
+    pub fn clearance(&self, pos: Point2, width: f32, height: f32) -> f32 {
+        let distance = |to: Point2| wrapped_delta(pos, to, width, height).norm();
+        let stars = self.stars.iter().map(|s| distance(s.pos) - s.radius);
+        let obstacles = self.obstacles.iter().map(|o| distance(o.pos) - o.radius);
+        stars.chain(obstacles).fold(f32::MAX, f32::min)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let text =
+            "# a busy map\nstar 0 0 40 50\n\nobstacle -200 100 30\nwormhole -300 -200 300 200 20\n";
+        let arena = Arena::parse(text).unwrap();
+        assert_eq!(arena.stars.len(), 1);
+        assert_eq!(arena.stars[0].gravity, 50.0);
+        assert_eq!(arena.obstacles[0].pos, Point2::new(-200.0, 100.0));
+        assert_eq!(arena.wormholes[0].b, Point2::new(300.0, 200.0));
+
+        assert!(Arena::parse("star 0 0 40").is_err());
+        assert!(Arena::parse("star 0 0 big 50").is_err());
+        assert!(Arena::parse("blackhole 0 0 40 50").is_err());
+    }
+
+    #[test]
+    fn test_gravity_and_wormholes() {
+        let arena = Arena::parse("star 0 0 10 50\nwormhole 100 0 -100 0 5").unwrap();
+
+        let pull = arena.gravity(Point2::new(0.0, 100.0), 800.0, 600.0);
+        assert!((pull.y + 50.0).abs() < 0.001);
+        assert!(arena.gravity(Point2::new(0.0, 200.0), 800.0, 600.0).norm() < pull.norm());
+
+        let exit = arena.wormhole_exit(
+            Point2::new(101.0, 0.0),
+            Vector2::new(0.0, 10.0),
+            800.0,
+            600.0,
+        );
+        assert_eq!(exit, Some(Point2::new(-100.0, 6.0)));
+        assert_eq!(
+            arena.wormhole_exit(exit.unwrap(), Vector2::new(0.0, 10.0), 800.0, 600.0),
+            None
+        );
+        assert_eq!(
+            arena.wormhole_exit(
+                Point2::new(101.0, 0.0),
+                Vector2::new(0.0, 0.0),
+                800.0,
+                600.0
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_across_the_seam() {
+        let arena = Arena::parse("star 390 0 10 50\nobstacle 0 290 20").unwrap();
+
+        // The star is just the other side of the left edge, so it pulls that way.
+        assert!(arena.gravity(Point2::new(-390.0, 0.0), 800.0, 600.0).x < 0.0);
+        assert!(arena.star_at(Point2::new(-395.0, 0.0), 10.0, 800.0, 600.0).is_some());
+
+        let (normal, _) = arena
+            .obstacle_push(Point2::new(0.0, -295.0), 10.0, 800.0, 600.0)
+            .unwrap();
+        assert!(normal.y > 0.0);
+        assert!(arena.clearance(Point2::new(0.0, -295.0), 800.0, 600.0) < 0.0);
+    }
+}
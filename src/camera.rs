@@ -0,0 +1,130 @@
+use crate::{collision, Point2, Vector2};
+use ggez::{graphics, Context, GameResult};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64Mcg;
+
+/// What part of the world is on screen. In the classic game the whole world fits and the camera
+/// never moves; in a bigger world it follows the local ship around.
+#[derive(Debug, Clone, Copy)]
+pub struct Camera {
+    /// The point in the world at the middle of the screen.
+    pub pos: Point2,
+    /// How far the camera has moved altogether, without wrapping round. The starfield scrolls by
+    /// this, so it doesn't jump when the ship crosses the edge of the world.
+    pub travelled: Vector2,
+    pub view_width: f32,
+    pub view_height: f32,
+    pub world_width: f32,
+    pub world_height: f32,
+}
+
+impl Camera {
+    pub fn new(view_width: f32, view_height: f32, world_width: f32, world_height: f32) -> Camera {
+        Camera {
+            pos: Point2::origin(),
+            travelled: Vector2::new(0.0, 0.0),
+            view_width,
+            view_height,
+            world_width,
+            world_height,
+        }
+    }
+
+    /// Centres the camera on `target`. Along an axis where the whole world already fits on
+    /// screen it stays put, so the classic game looks just as it always has.
+    pub fn follow(&mut self, target: Point2) {
+        let mut delta = self.offset(target);
+        if self.world_width <= self.view_width {
+            delta.x = 0.0;
+        }
+        if self.world_height <= self.view_height {
+            delta.y = 0.0;
+        }
+
+        self.travelled += delta;
+        self.pos += delta;
+        self.pos.x = wrap(self.pos.x, self.world_width);
+        self.pos.y = wrap(self.pos.y, self.world_height);
+    }
+
+    /// Where `point` is relative to the camera, going the short way round the world.
+    pub fn offset(&self, point: Point2) -> Vector2 {
+        collision::wrapped_delta(self.pos, point, self.world_width, self.world_height)
+    }
+}
+
+// Brings a coordinate back into a world of the given size, centred on the origin.
+fn wrap(value: f32, size: f32) -> f32 {
+    (value + size / 2.0).rem_euclid(size) - size / 2.0
+}
+
+// How many stars there are in each layer of the starfield, how far away the layer is (as how
+// much it moves compared to the world), how bright its stars are and how big.
+const STAR_LAYERS: [(usize, f32, f32, f32); 3] = [
+    (60, 0.1, 0.3, 1.0),
+    (40, 0.25, 0.5, 1.0),
+    (25, 0.5, 0.8, 1.5),
+];
+
+/// A few layers of background stars that slide past at different speeds as the camera moves,
+/// so players can tell they're moving even with nothing else in sight.
+#[derive(Debug)]
+pub struct Starfield {
+    // Each star's place on a screen-sized tile, and the layer it's in.
+    stars: Vec<(Point2, usize)>,
+}
+
+impl Starfield {
+    pub fn new(seed: u64, view_width: f32, view_height: f32) -> Starfield {
+        let mut rng = Pcg64Mcg::seed_from_u64(seed);
+        let mut stars = Vec::new();
+        for (layer, &(count, ..)) in STAR_LAYERS.iter().enumerate() {
+            for _ in 0..count {
+                let x = rng.gen_range(0.0, view_width);
+                let y = rng.gen_range(0.0, view_height);
+                stars.push((Point2::new(x, y), layer));
+            }
+        }
+
+        Starfield { stars }
+    }
+
+    pub fn draw(&self, ctx: &mut Context, camera: &Camera) -> GameResult {
+        let mut builder = graphics::MeshBuilder::new();
+        for &(star, layer) in &self.stars {
+            let (_, depth, brightness, size) = STAR_LAYERS[layer];
+            let x = (star.x - camera.travelled.x * depth).rem_euclid(camera.view_width);
+            let y = (star.y + camera.travelled.y * depth).rem_euclid(camera.view_height);
+            builder.circle(
+                graphics::DrawMode::fill(),
+                Point2::new(x, y),
+                size,
+                0.5,
+                graphics::Color::new(brightness, brightness, brightness, 1.0),
+            );
+        }
+
+        let mesh = builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, graphics::DrawParam::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_follow() {
+        // The classic world fits on screen, so the camera doesn't budge.
+        let mut camera = Camera::new(800.0, 600.0, 800.0, 600.0);
+        camera.follow(Point2::new(100.0, 100.0));
+        assert_eq!(camera.pos, Point2::origin());
+
+        // A bigger world scrolls, and wraps round without the distance travelled jumping.
+        let mut camera = Camera::new(800.0, 600.0, 3200.0, 2400.0);
+        camera.follow(Point2::new(1500.0, 0.0));
+        camera.follow(Point2::new(-1500.0, 0.0));
+        assert_eq!(camera.pos, Point2::new(-1500.0, 0.0));
+        assert_eq!(camera.travelled, Vector2::new(1700.0, 0.0));
+    }
+}
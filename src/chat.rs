@@ -0,0 +1,256 @@
+use ggez::graphics::Color;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+/// The most characters a chat message can have.
+pub const MAX_CHAT_LEN: usize = 80;
+
+// No more than this many messages can be sent in any few seconds.
+const RATE_LIMIT_COUNT: usize = 3;
+const RATE_LIMIT_WINDOW: f32 = 5.0;
+
+// Seconds between sending a message again to peers who haven't acknowledged it, and how many
+// times it's sent before we give up on them.
+const RESEND_TIME: f32 = 0.5;
+const MAX_SENDS: u32 = 10;
+
+// How many of each peer's latest message numbers we remember. Anything older than those is long
+// past being sent again, so it can only be a repeat.
+const SEEN_WINDOW: usize = 32;
+
+// Seconds a line stays in the log, the last few of which it spends fading out.
+const LINE_TIME: f32 = 10.0;
+const FADE_TIME: f32 = 2.0;
+const MAX_LINES: usize = 6;
+
+/// A line in the chat log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub name: String,
+    pub text: String,
+    pub color: Color,
+    /// Seconds since the line turned up.
+    pub age: f32,
+}
+
+impl Line {
+    pub fn new(name: &str, text: &str, color: Color) -> Line {
+        Line {
+            name: name.to_string(),
+            text: text.to_string(),
+            color,
+            age: 0.0,
+        }
+    }
+
+    /// How opaque the line is: fully until it starts fading out towards the end of its time.
+    pub fn alpha(&self) -> f32 {
+        ((LINE_TIME - self.age) / FADE_TIME).clamp(0.0, 1.0)
+    }
+}
+
+// A message of ours that some peers haven't acknowledged yet.
+#[derive(Debug)]
+struct Outgoing {
+    seq: u32,
+    text: String,
+    waiting_for: HashSet<u32>,
+    resend_timeout: f32,
+    sends: u32,
+}
+
+/// Keeps the chat going over a channel that can drop messages. Every message is numbered and
+/// sent again until each peer that was around to hear it says it has, and peers ignore the
+/// repeats they've already seen. It also keeps the log of recent lines for showing on screen.
+#[derive(Debug, Default)]
+pub struct Chat {
+    next_seq: u32,
+    outgoing: Vec<Outgoing>,
+    // The numbers of the messages we've already shown, by sender id.
+    seen: HashMap<u32, BTreeSet<u32>>,
+    // How long ago each of our recent messages was sent.
+    recent_sends: Vec<f32>,
+    lines: VecDeque<Line>,
+}
+
+impl Chat {
+    /// Starts sending `text` to the peers with the given ids. Returns the message's number and
+    /// the tidied up text to put on the wire, or `None` if there's nothing to say or we've been
+    /// saying too much.
+    pub fn send<I: IntoIterator<Item = u32>>(
+        &mut self,
+        text: &str,
+        peers: I,
+    ) -> Option<(u32, String)> {
+        let text = clean(text);
+        if text.is_empty() || self.recent_sends.len() >= RATE_LIMIT_COUNT {
+            return None;
+        }
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.recent_sends.push(0.0);
+        self.outgoing.push(Outgoing {
+            seq,
+            text: text.clone(),
+            waiting_for: peers.into_iter().collect(),
+            resend_timeout: RESEND_TIME,
+            sends: 1,
+        });
+        self.outgoing.retain(|m| !m.waiting_for.is_empty());
+
+        Some((seq, text))
+    }
+
+    /// The peer with id `peer` has our message `seq`.
+    pub fn acknowledge(&mut self, seq: u32, peer: u32) {
+        for message in self.outgoing.iter_mut().filter(|m| m.seq == seq) {
+            message.waiting_for.remove(&peer);
+        }
+        self.outgoing.retain(|m| !m.waiting_for.is_empty());
+    }
+
+    /// Stops waiting on a peer that has left, and forgets what they've said.
+    pub fn forget_peer(&mut self, peer: u32) {
+        self.seen.remove(&peer);
+        for message in &mut self.outgoing {
+            message.waiting_for.remove(&peer);
+        }
+        self.outgoing.retain(|m| !m.waiting_for.is_empty());
+    }
+
+    /// Advances the clock by `dt` seconds, returning the messages that are due to be sent again.
+    pub fn update(&mut self, dt: f32) -> Vec<(u32, String)> {
+        for line in &mut self.lines {
+            line.age += dt;
+        }
+        self.lines.retain(|line| line.age <= LINE_TIME);
+
+        for age in &mut self.recent_sends {
+            *age += dt;
+        }
+        self.recent_sends.retain(|&age| age < RATE_LIMIT_WINDOW);
+
+        let mut resends = Vec::new();
+        for message in &mut self.outgoing {
+            message.resend_timeout -= dt;
+            if message.resend_timeout <= 0.0 {
+                message.resend_timeout = RESEND_TIME;
+                message.sends += 1;
+                resends.push((message.seq, message.text.clone()));
+            }
+        }
+        self.outgoing.retain(|m| m.sends < MAX_SENDS);
+
+        resends
+    }
+
+    /// A message from the peer with id `sender`. It's logged the first time it turns up and
+    /// ignored after that. Either way it's worth acknowledging, since our last acknowledgement
+    /// might have gone astray.
+    pub fn receive(&mut self, sender: u32, seq: u32, name: &str, text: &str, color: Color) {
+        let seen = self.seen.entry(sender).or_default();
+        let oldest = seen.iter().next().cloned();
+        let stale = seen.len() >= SEEN_WINDOW && oldest.is_some_and(|oldest| seq < oldest);
+        if stale || !seen.insert(seq) {
+            return;
+        }
+        if seen.len() > SEEN_WINDOW {
+            if let Some(oldest) = oldest {
+                seen.remove(&oldest);
+            }
+        }
+
+        self.push_line(Line::new(name, &clean(text), color));
+    }
+
+    /// Adds a line to the log, such as one of our own messages.
+    pub fn push_line(&mut self, line: Line) {
+        self.lines.push_back(line);
+        while self.lines.len() > MAX_LINES {
+            self.lines.pop_front();
+        }
+    }
+
+    /// The log, oldest line first.
+    pub fn lines(&self) -> impl Iterator<Item = &Line> {
+        self.lines.iter()
+    }
+}
+
+/// Tidies up a message: no control characters, no space at either end and no longer than
+/// `MAX_CHAT_LEN`.
+pub fn clean(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>()
+        .trim()
+        .chars()
+        .take(MAX_CHAT_LEN)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ggez::graphics::WHITE;
+
+    #[test]
+    fn test_resend_until_acknowledged() {
+        let mut chat = Chat::default();
+        let (seq, text) = chat.send("  hello\n", vec![1, 2]).unwrap();
+        assert_eq!(text, "hello");
+
+        // Nobody has answered yet, so it goes out again.
+        assert_eq!(chat.update(RESEND_TIME), vec![(seq, text.clone())]);
+
+        // Once everyone has it we stop.
+        chat.acknowledge(seq, 1);
+        chat.forget_peer(2);
+        assert!(chat.update(RESEND_TIME).is_empty());
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let mut chat = Chat::default();
+        for _ in 0..RATE_LIMIT_COUNT {
+            assert!(chat.send("spam", vec![]).is_some());
+        }
+        assert!(chat.send("spam", vec![]).is_none());
+
+        chat.update(RATE_LIMIT_WINDOW);
+        assert!(chat.send("spam", vec![]).is_some());
+        assert!(chat.send("   ", vec![]).is_none());
+    }
+
+    #[test]
+    fn test_receive_once_and_fade() {
+        let mut chat = Chat::default();
+        chat.receive(7, 0, "Ripley", "hi", WHITE);
+        chat.receive(7, 0, "Ripley", "hi", WHITE);
+        chat.receive(7, 1, "Ripley", &"x".repeat(200), WHITE);
+        assert_eq!(chat.lines().count(), 2);
+        assert_eq!(chat.lines().last().unwrap().text.len(), MAX_CHAT_LEN);
+
+        chat.update(LINE_TIME - FADE_TIME / 2.0);
+        assert!((chat.lines().next().unwrap().alpha() - 0.5).abs() < 0.001);
+        chat.update(FADE_TIME);
+        assert_eq!(chat.lines().count(), 0);
+    }
+
+    #[test]
+    fn test_seen_is_bounded() {
+        let mut chat = Chat::default();
+        for seq in 0..100 {
+            chat.receive(7, seq, "Ripley", "hi", WHITE);
+        }
+        assert_eq!(chat.seen[&7].len(), SEEN_WINDOW);
+
+        // A very late repeat of an old message is still ignored.
+        chat.update(LINE_TIME + 1.0);
+        chat.receive(7, 0, "Ripley", "hi", WHITE);
+        assert_eq!(chat.lines().count(), 0);
+
+        chat.forget_peer(7);
+        assert!(chat.seen.is_empty());
+    }
+}
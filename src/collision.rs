@@ -0,0 +1,251 @@
+use crate::actor::Actor;
+use crate::{Point2, Vector2};
+use std::collections::HashMap;
+
+/// The shortest vector from `from` to `to` in a world that wraps around at its edges. Going off
+/// one side brings you back on the other, so the short way round might be across the seam.
+pub fn wrapped_delta(from: Point2, to: Point2, width: f32, height: f32) -> Vector2 {
+    let wrap = |d: f32, size: f32| d - size * (d / size).round();
+    let d = to - from;
+    Vector2::new(wrap(d.x, width), wrap(d.y, height))
+}
+
+/// Turns a point from an actor's polygon, which is drawn in screen space (+y down) around the
+/// actor's origin, into world space given where the actor is and its facing.
+fn local_to_world(actor: &Actor, pos: Point2, point: &Point2) -> Point2 {
+    let (sin, cos) = actor.facing.sin_cos();
+    let x = point.x * cos - point.y * sin;
+    let y = -point.x * sin - point.y * cos;
+    pos + Vector2::new(x, y)
+}
+
+/// The actor's outline in world space, turned to match its facing, as if it were at `pos`.
+pub fn world_polygon(actor: &Actor, pos: Point2) -> Vec<Point2> {
+    actor
+        .polygon()
+        .iter()
+        .map(|p| local_to_world(actor, pos, p))
+        .collect()
+}
+
+/// The radius of the smallest circle around the actor's origin that contains all of it.
+pub fn bounding_radius(actor: &Actor) -> f32 {
+    actor
+        .polygon()
+        .iter()
+        .map(|p| p.coords.norm())
+        .fold(actor.bbox_size, f32::max)
+}
+
+/// Cuts a polygon into triangles fanned out from `center`. All our outlines are star-shaped around
+/// the actor's origin, so every one of these triangles lies inside the polygon and together they
+/// cover it, concave bits and all.
+fn triangles(center: Point2, polygon: &[Point2]) -> Vec<[Point2; 3]> {
+    (0..polygon.len())
+        .map(|i| [center, polygon[i], polygon[(i + 1) % polygon.len()]])
+        .collect()
+}
+
+/// Projects a convex shape onto an axis, giving the interval it covers.
+fn project(shape: &[Point2], axis: Vector2) -> (f32, f32) {
+    shape
+        .iter()
+        .map(|p| p.coords.dot(&axis))
+        .fold((f32::MAX, f32::MIN), |(min, max), d| {
+            (min.min(d), max.max(d))
+        })
+}
+
+/// The separating axis test: two convex shapes overlap unless there's an edge normal of one of
+/// them along which their projections don't meet.
+fn convex_overlap(a: &[Point2], b: &[Point2]) -> bool {
+    let edges = |shape: &[Point2]| {
+        (0..shape.len())
+            .map(|i| shape[(i + 1) % shape.len()] - shape[i])
+            .collect::<Vec<_>>()
+    };
+
+    edges(a).iter().chain(edges(b).iter()).all(|edge| {
+        let axis = Vector2::new(-edge.y, edge.x);
+        let (a_min, a_max) = project(a, axis);
+        let (b_min, b_max) = project(b, axis);
+        a_max >= b_min && b_max >= a_min
+    })
+}
+
+/// Checks whether the outlines of two actors overlap in a world of the given size. The world wraps,
+/// so we look at `b` wherever it's closest to `a`, which might be on the far side of a seam.
+pub fn actors_collide(a: &Actor, b: &Actor, width: f32, height: f32) -> bool {
+    let delta = wrapped_delta(a.pos, b.pos, width, height);
+    let reach = bounding_radius(a) + bounding_radius(b);
+    if delta.norm_squared() > reach.powi(2) {
+        return false;
+    }
+
+    let b_pos = a.pos + delta;
+    let a_triangles = triangles(a.pos, &world_polygon(a, a.pos));
+    let b_triangles = triangles(b_pos, &world_polygon(b, b_pos));
+    a_triangles
+        .iter()
+        .any(|ta| b_triangles.iter().any(|tb| convex_overlap(ta, tb)))
+}
+
+/// How heavy an actor is for the purposes of bouncing off things. We treat everything as a solid
+/// disc, so mass goes with the square of size.
+fn mass(actor: &Actor) -> f32 {
+    actor.bbox_size.powi(2)
+}
+
+/// Works out how two actors that have run into each other bounce apart, as if they were perfectly
+/// elastic discs. Returns the change in velocity for each of them, or `None` if they're already
+/// moving apart (or sitting right on top of each other, so there's no telling which way is apart).
+pub fn bounce(a: &Actor, b: &Actor, width: f32, height: f32) -> Option<(Vector2, Vector2)> {
+    let delta = wrapped_delta(a.pos, b.pos, width, height);
+    let distance = delta.norm();
+    if distance < 0.001 {
+        return None;
+    }
+
+    let normal = delta / distance;
+    let approach = (a.velocity - b.velocity).dot(&normal);
+    if approach <= 0.0 {
+        return None;
+    }
+
+    let (mass_a, mass_b) = (mass(a), mass(b));
+    let impulse = 2.0 * approach / (1.0 / mass_a + 1.0 / mass_b);
+    Some((-normal * impulse / mass_a, normal * impulse / mass_b))
+}
+
+/// A broad phase for collision detection. Actors are dropped into the cells of a coarse grid laid
+/// over the world, so when we want to know what might be touching something we only have to look
+/// at the actors sharing its cells instead of every actor in the world. The grid wraps around at
+/// the edges just like the world does.
+pub struct SpatialGrid {
+    cell_size: f32,
+    columns: i32,
+    rows: i32,
+    width: f32,
+    height: f32,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl SpatialGrid {
+    pub fn new(width: f32, height: f32, cell_size: f32) -> SpatialGrid {
+        SpatialGrid {
+            cell_size,
+            columns: (width / cell_size).ceil().max(1.0) as i32,
+            rows: (height / cell_size).ceil().max(1.0) as i32,
+            width,
+            height,
+            cells: HashMap::new(),
+        }
+    }
+
+    /// The cells, wrapped onto the grid, that the actor's bounding box touches.
+    fn cells_for(&self, actor: &Actor) -> Vec<(i32, i32)> {
+        let radius = bounding_radius(actor);
+        let cell = |v: f32, offset: f32| ((v + offset) / self.cell_size).floor() as i32;
+
+        let x0 = cell(actor.pos.x - radius, self.width / 2.0);
+        let x1 = cell(actor.pos.x + radius, self.width / 2.0).min(x0 + self.columns - 1);
+        let y0 = cell(actor.pos.y - radius, self.height / 2.0);
+        let y1 = cell(actor.pos.y + radius, self.height / 2.0).min(y0 + self.rows - 1);
+
+        let mut cells = Vec::new();
+        for x in x0..=x1 {
+            for y in y0..=y1 {
+                cells.push((x.rem_euclid(self.columns), y.rem_euclid(self.rows)));
+            }
+        }
+        cells
+    }
+
+    pub fn insert(&mut self, index: usize, actor: &Actor) {
+        for cell in self.cells_for(actor) {
+            self.cells.entry(cell).or_default().push(index);
+        }
+    }
+
+    /// The indices of everything inserted that might be touching the given actor.
+    pub fn query(&self, actor: &Actor) -> Vec<usize> {
+        let mut found: Vec<usize> = self
+            .cells_for(actor)
+            .iter()
+            .filter_map(|cell| self.cells.get(cell))
+            .flatten()
+            .cloned()
+            .collect();
+        found.sort();
+        found.dedup();
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actor::{RockSize, ShipClass};
+
+    #[test]
+    fn test_facing_matters() {
+        // A ship pointing straight up, with a shot just off its nose to the right.
+        let player = Actor::create_player("self".to_string(), ShipClass::Balanced);
+        let mut shot = Actor::create_shot("self".to_string());
+        shot.pos = Point2::new(10.0, 10.0);
+        assert!(!actors_collide(&player, &shot, 800.0, 600.0));
+
+        // Turning the ship to face the shot puts its nose right through it.
+        let mut player = player;
+        player.facing = std::f32::consts::FRAC_PI_4;
+        assert!(actors_collide(&player, &shot, 800.0, 600.0));
+    }
+
+    #[test]
+    fn test_collide_across_seam() {
+        let mut rock = Actor::create_rock("self".to_string(), RockSize::Medium, 0);
+        rock.pos = Point2::new(396.0, 0.0);
+        let mut player = Actor::create_player("self".to_string(), ShipClass::Balanced);
+        player.pos = Point2::new(-396.0, 0.0);
+
+        let delta = wrapped_delta(rock.pos, player.pos, 800.0, 600.0);
+        assert!((delta.norm() - 8.0).abs() < 0.001);
+        assert!(actors_collide(&rock, &player, 800.0, 600.0));
+    }
+
+    #[test]
+    fn test_grid_wraps() {
+        let mut grid = SpatialGrid::new(800.0, 600.0, 50.0);
+
+        let mut rock = Actor::create_rock("self".to_string(), RockSize::Medium, 0);
+        rock.pos = Point2::new(395.0, 0.0);
+        grid.insert(0, &rock);
+
+        let mut shot = Actor::create_shot("self".to_string());
+        shot.pos = Point2::new(-398.0, 0.0);
+        assert_eq!(grid.query(&shot), vec![0]);
+
+        shot.pos = Point2::new(0.0, 0.0);
+        assert!(grid.query(&shot).is_empty());
+    }
+
+    #[test]
+    fn test_head_on_bounce() {
+        let mut a = Actor::create_rock("self".to_string(), RockSize::Medium, 0);
+        a.pos = Point2::new(-5.0, 0.0);
+        a.velocity = Vector2::new(10.0, 0.0);
+        let mut b = Actor::create_rock("self".to_string(), RockSize::Medium, 1);
+        b.pos = Point2::new(5.0, 0.0);
+        b.velocity = Vector2::new(-10.0, 0.0);
+
+        // Equal masses meeting head on swap velocities.
+        let (dv_a, dv_b) = bounce(&a, &b, 800.0, 600.0).unwrap();
+        assert!((a.velocity + dv_a - b.velocity).norm() < 0.001);
+        assert!((b.velocity + dv_b - a.velocity).norm() < 0.001);
+
+        // Once they're heading apart they're left alone.
+        a.velocity = Vector2::new(-10.0, 0.0);
+        b.velocity = Vector2::new(10.0, 0.0);
+        assert!(bounce(&a, &b, 800.0, 600.0).is_none());
+    }
+}
@@ -0,0 +1,139 @@
+use crate::actor::ShipClass;
+
+/// How a ship handles. Everything is in pixels and radians per second (or per second squared for
+/// accelerations), and it can all be tuned from a config file without recompiling.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlightModel {
+    /// Acceleration from the main engine.
+    pub thrust: f32,
+    /// Acceleration when thrusting backwards.
+    pub reverse_thrust: f32,
+    /// Acceleration when sliding sideways.
+    pub strafe_thrust: f32,
+    /// The fastest the ship can spin.
+    pub turn_rate: f32,
+    /// How quickly the ship gets up to (or back down from) its turn rate.
+    pub turn_accel: f32,
+    /// The fraction of its velocity the ship loses every second. Zero is the classic frictionless
+    /// feel, where ships never slow down.
+    pub drag: f32,
+    pub max_speed: f32,
+}
+
+impl Default for FlightModel {
+    fn default() -> Self {
+        FlightModel {
+            thrust: 100.0,
+            reverse_thrust: 50.0,
+            strafe_thrust: 50.0,
+            turn_rate: 3.0,
+            turn_accel: 30.0,
+            drag: 0.0,
+            max_speed: 250.0,
+        }
+    }
+}
+
+impl FlightModel {
+    /// Reads a flight model from `key = value` lines. Anything it doesn't mention is left at its
+    /// default. Blank lines and lines starting with `#` are skipped.
+    pub fn parse(text: &str) -> Result<FlightModel, String> {
+        FlightModel::parse_section(FlightModel::default(), text, "")
+    }
+
+    /// Like `parse`, but starts from `base` and also applies the lines under a `[section]`
+    /// header, where the section is named after a class of ship. Lines before the first header
+    /// apply to every section. Lines in other sections are still checked, so a typo anywhere in
+    /// the file is reported.
+    pub fn parse_section(
+        base: FlightModel,
+        text: &str,
+        section: &str,
+    ) -> Result<FlightModel, String> {
+        let mut model = base;
+        let mut scratch = base;
+        let mut current = "";
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                current = line[1..line.len() - 1].trim();
+                if ShipClass::ALL.iter().all(|class| class.name() != current) {
+                    return Err(format!(
+                        "line {}: unknown ship class: {}",
+                        number + 1,
+                        current
+                    ));
+                }
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim();
+            let value = parts
+                .next()
+                .ok_or_else(|| format!("line {}: expected key = value", number + 1))?
+                .trim();
+            let value: f32 = value
+                .parse()
+                .map_err(|_| format!("line {}: invalid number: {}", number + 1, value))?;
+
+            let target = if current.is_empty() || current == section {
+                &mut model
+            } else {
+                &mut scratch
+            };
+            let field = match key {
+                "thrust" => &mut target.thrust,
+                "reverse_thrust" => &mut target.reverse_thrust,
+                "strafe_thrust" => &mut target.strafe_thrust,
+                "turn_rate" => &mut target.turn_rate,
+                "turn_accel" => &mut target.turn_accel,
+                "drag" => &mut target.drag,
+                "max_speed" => &mut target.max_speed,
+                _ => return Err(format!("line {}: unknown setting: {}", number + 1, key)),
+            };
+            *field = value;
+        }
+
+        Ok(model)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let model = FlightModel::parse("# floaty\ndrag = 0.5\n\n  max_speed=180 \n").unwrap();
+        assert_eq!(model.drag, 0.5);
+        assert_eq!(model.max_speed, 180.0);
+        assert_eq!(model.thrust, FlightModel::default().thrust);
+
+        assert!(FlightModel::parse("drag").is_err());
+        assert!(FlightModel::parse("drag = lots").is_err());
+        assert!(FlightModel::parse("warp = 9").is_err());
+    }
+
+    #[test]
+    fn test_parse_section() {
+        let text = "drag = 0.5\n[scout]\nthrust = 200\n[gunship]\nthrust = 60\n";
+        let base = FlightModel::default();
+
+        let scout = FlightModel::parse_section(base, text, "scout").unwrap();
+        assert_eq!(scout.drag, 0.5);
+        assert_eq!(scout.thrust, 200.0);
+
+        let other = FlightModel::parse_section(base, text, "balanced").unwrap();
+        assert_eq!(other.drag, 0.5);
+        assert_eq!(other.thrust, base.thrust);
+
+        assert!(FlightModel::parse_section(base, "[scout]\nwarp = 9", "balanced").is_err());
+        assert!(FlightModel::parse_section(base, "[scuot]\nthrust = 9", "scout").is_err());
+    }
+}
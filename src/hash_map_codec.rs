@@ -5,12 +5,21 @@ use tokio_codec::{Decoder, Encoder};
 
 pub struct HashMapCodec;
 
+/// How many bytes the given map takes up on the wire.
+pub fn encoded_len(hash_map: &HashMap<String, f64>) -> usize {
+    hash_map
+        .encode::<u32>()
+        .map(|bytes| bytes.len())
+        .unwrap_or(0)
+}
+
 impl Decoder for HashMapCodec {
     type Item = HashMap<String, f64>;
     type Error = io::Error;
 
     fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<HashMap<String, f64>>, io::Error> {
-        let decoded_map = <HashMap<String, f64>>::decode::<u32>(&buf).expect("unable to decode");
+        let decoded_map = <HashMap<String, f64>>::decode::<u32>(buf)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "unable to decode"))?;
 
         Ok(Some(decoded_map))
     }
@@ -1,127 +1,120 @@
 mod actor;
+mod arena;
+mod camera;
+mod chat;
+mod collision;
+mod flight;
 mod hash_map_codec;
-
-use actor::Actor;
+mod net_stats;
+mod network;
+mod settings;
+mod weapon;
+mod world;
+
+pub use actor::{Actor, ActorType, PickupKind, RockSize, SaucerSize, ShipClass};
+pub use arena::Arena;
+use camera::{Camera, Starfield};
+use chat::Chat;
+pub use flight::FlightModel;
 use ggez::{
     audio::{self, SoundSource},
     event::{EventHandler, KeyCode, KeyMods},
     graphics, nalgebra as na, timer, Context, GameResult,
 };
 pub use hash_map_codec::HashMapCodec;
+use net_stats::NetStats;
+pub use network::Network;
+use network::{ConnectionStatus, Message};
+use settings::MAX_NAME_LEN;
+pub use settings::{Corner, Settings};
 use std::collections::HashMap;
-use std::sync::mpsc;
+use std::time::{Duration, Instant};
+pub use weapon::{Weapon, WEAPONS};
+use weapon::{MAX_PROJECTILES, MAX_SPREAD};
+pub use world::{Event, InputState, World};
 
 pub type Point2 = na::Point2<f32>;
 pub type Vector2 = na::Vector2<f32>;
 
-/// Create a unit vector representing the given angle (in radians).
-fn vec_from_angle(angle: f32) -> Vector2 {
-    let vx = angle.sin();
-    let vy = angle.cos();
-    Vector2::new(vx, vy)
-}
+// Seconds between pings to measure round-trip times.
+const PING_TIME: f32 = 1.0;
+// How long a peer can go quiet before we take it they've gone without saying goodbye.
+const PEER_TIMEOUT: Duration = Duration::from_secs(5);
+// Seconds between losing a ship and getting the next one.
+const RESPAWN_TIME: f32 = 3.0;
+// How many times a second an invulnerable ship blinks.
+const BLINK_RATE: f32 = 8.0;
+// The colours players are given when they don't pick one, in order of id.
+const PLAYER_COLORS: [u32; 8] = [
+    0xffa030, 0x40e0ff, 0xff60ff, 0xffff50, 0x80a0ff, 0xff90b0, 0xa0ff60, 0xc080ff,
+];
+// What other players look like until we hear what colour they've picked.
+const UNKNOWN_PLAYER_COLOR: graphics::Color = graphics::Color {
+    r: 1.0,
+    g: 0.0,
+    b: 0.0,
+    a: 1.0,
+};
 
-/// Makes a random `Vector2` with the given max magnitude.
-fn random_vec(max_magnitude: f32) -> Vector2 {
-    let angle = rand::random::<f32>() * 2.0 * std::f32::consts::PI;
-    let mag = rand::random::<f32>() * max_magnitude;
-    vec_from_angle(angle) * (mag)
+// How wide the radar is on screen, and how far it sits from the edges.
+const RADAR_WIDTH: f32 = 150.0;
+const RADAR_MARGIN: f32 = 10.0;
+
+/// How much of the world is on screen at once. It's the same for everyone whatever their window,
+/// and each window is scaled to fit it. The classic world is exactly this size.
+pub const VIEW_WIDTH: f32 = 800.0;
+pub const VIEW_HEIGHT: f32 = 600.0;
+
+/// Translates the world coordinate system to coordinates suitable for the audio system, so
+/// things sound like they're coming from where they are on screen.
+fn world_to_audio_coords(camera: &Camera, point: Point2) -> [f32; 3] {
+    let offset = camera.offset(point);
+    let x = offset.x * 2.0 / camera.view_width;
+    let y = offset.y * 2.0 / camera.view_height;
+    let z = 0.0;
+    [x, y, z]
 }
 
-const MAX_ROCK_VEL: f32 = 50.0;
-
-/// Create the given number of rocks. Makes sure that none of them are within the given exclusion
-/// zone (nominally the player). Note that this *could* create rocks outside the bounds of the
-/// playing field, so it should be called before `wrap_actor_position()` happens.
-fn create_rocks(num: i32, exclusion: Point2, min_radius: f32, max_radius: f32) -> Vec<Actor> {
-    assert!(max_radius > min_radius);
-    let new_rock = |_| {
-        let mut rock = Actor::create_rock("self".to_string());
-        let r_angle = rand::random::<f32>() * 2.0 * std::f32::consts::PI;
-        let r_distance = rand::random::<f32>() * (max_radius - min_radius) + min_radius;
-        rock.pos = exclusion + vec_from_angle(r_angle) * r_distance;
-        rock.velocity = random_vec(MAX_ROCK_VEL);
-        rock
-    };
-    (0..num).map(new_rock).collect()
+/// Finds a piece of text smuggled through a message as the end of a key starting with `prefix`,
+/// since the values can only be numbers.
+fn message_text<'a>(map: &'a Message, prefix: &str) -> Option<&'a str> {
+    map.keys()
+        .find(|k| k.starts_with(prefix))
+        .map(|k| &k[prefix.len()..])
 }
 
-// Now we make functions to handle physics. We do simple Newtonian physics (so we do have
-// inertia), and cap the max speed so that we don't have to worry too much about small objects
-// clipping through each other.
-//
-// Our unit of world space is simply pixels, though we do transform the coordinate system so that
-// +y is up and -y is down.
-
-const SHOT_SPEED: f32 = 200.0;
-
-// Acceleration in pixels per second.
-const PLAYER_THRUST: f32 = 100.0;
-// Rotation in radians per second.
-const PLAYER_TURN_RATE: f32 = 3.0;
-// Seconds between shots.
-const PLAYER_SHOT_TIME: f32 = 0.5;
-
-fn player_handle_input(actor: &mut Actor, input: &InputState, dt: f32) {
-    actor.facing += dt * PLAYER_TURN_RATE * input.xaxis;
-
-    if input.yaxis > 0.0 {
-        player_thrust(actor, dt);
-    }
+/// Reads a number from a message, if it's there and it's an actual number. Anything could turn up
+/// on the multicast group, so nothing can be taken for granted, and a NaN or infinity let in here
+/// would go on to poison every sum and comparison it touches.
+fn field(map: &Message, key: &str) -> Option<f64> {
+    map.get(key).cloned().filter(|value| value.is_finite())
 }
 
-fn player_thrust(actor: &mut Actor, dt: f32) {
-    let direction_vector = vec_from_angle(actor.facing);
-    let thrust_vector = direction_vector * (PLAYER_THRUST);
-    actor.velocity += thrust_vector * (dt);
+/// Adds the seed and level of the rocks we're playing among to a message. The seed is sent in
+/// halves, since a whole one won't fit in a message's number.
+fn insert_rock_field(map: &mut Message, seed: u64, level: i32) {
+    map.insert("seed_hi".to_string(), ((seed >> 32) as u32).into());
+    map.insert("seed_lo".to_string(), (seed as u32).into());
+    map.insert("level".to_string(), level.into());
 }
 
-fn update_actor_position(actor: &mut Actor, dt: f32) {
-    let dv = actor.velocity * (dt);
-    actor.pos += dv;
-    actor.facing += actor.ang_vel;
+/// Reads back the seed and level put in a message by `insert_rock_field`.
+fn rock_field(map: &Message) -> Option<(u64, i32)> {
+    let seed = (field(map, "seed_hi")? as u64) << 32 | field(map, "seed_lo")? as u64;
+    Some((seed, field(map, "level")? as i32))
 }
 
-const MAX_PHYSICS_VEL: f32 = 250.0;
-
-fn clamp_actor_velocity(actor: &mut Actor) {
-    // Make sure players can't go too fast to get hectic.
-    let norm_sq = actor.velocity.norm_squared();
-    if norm_sq > MAX_PHYSICS_VEL.powi(2) {
-        actor.velocity = actor.velocity / norm_sq.sqrt() * MAX_PHYSICS_VEL;
+/// The color a pickup is drawn in, so you can tell what it is from across the screen.
+fn pickup_color(kind: PickupKind) -> graphics::Color {
+    match kind {
+        PickupKind::RapidFire => graphics::Color::new(1.0, 0.9, 0.2, 1.0),
+        PickupKind::Spread => graphics::Color::new(1.0, 0.5, 0.1, 1.0),
+        PickupKind::Piercing => graphics::Color::new(1.0, 0.3, 1.0, 1.0),
+        PickupKind::ExtraLife => graphics::Color::new(0.3, 1.0, 0.3, 1.0),
+        PickupKind::Shield => graphics::Color::new(0.3, 0.7, 1.0, 1.0),
     }
 }
 
-/// Takes an actor and wraps its position to the bounds of the screen, so if it goes off the left
-/// side of the screen it will re-enter on the right side and so on.
-fn wrap_actor_position(actor: &mut Actor, sx: f32, sy: f32) {
-    // Wrap screen.
-    let screen_x_bounds = sx / 2.0;
-    let screen_y_bounds = sy / 2.0;
-    if actor.pos.x > screen_x_bounds {
-        actor.pos.x -= sx;
-    } else if actor.pos.x < -screen_x_bounds {
-        actor.pos.x += sx;
-    };
-    if actor.pos.y > screen_y_bounds {
-        actor.pos.y -= sy;
-    } else if actor.pos.y < -screen_y_bounds {
-        actor.pos.y += sy;
-    }
-}
-
-fn handle_timed_life(actor: &mut Actor, dt: f32) {
-    actor.life -= dt;
-}
-
-/// Translates the world coordinate system to coordinates suitable for the audio system.
-fn world_to_audio_coords(screen_width: f32, screen_height: f32, point: Point2) -> [f32; 3] {
-    let x = point.x * 2.0 / screen_width;
-    let y = point.y * 2.0 / screen_height;
-    let z = 0.0;
-    [x, y, z]
-}
-
 /// A structure to contain the fonts, sounds, etc. that we need to hang on to; this is our "asset
 /// management system".  All the file names and such are just hard-coded.
 struct Assets {
@@ -148,178 +141,1055 @@ impl Assets {
     }
 }
 
-/// The `InputState` is exactly what it sounds like, it just keeps track of the user's input state
-/// so that we turn keyboard events into something state-based and device-independent.
-#[derive(Debug)]
-struct InputState {
-    xaxis: f32,
-    yaxis: f32,
-    fire: bool,
+/// How another player wants to be shown, and how they're getting on.
+struct Profile {
+    name: String,
+    color: graphics::Color,
+    score: i32,
+    kills: u32,
+    deaths: u32,
+    // Their average round trip to everyone else, as they measured it.
+    rtt: Option<Duration>,
 }
 
-impl Default for InputState {
-    fn default() -> Self {
-        InputState {
-            xaxis: 0.0,
-            yaxis: 0.0,
-            fire: false,
-        }
-    }
+/// One line of the scoreboard.
+struct ScoreRow {
+    name: String,
+    color: graphics::Color,
+    score: i32,
+    kills: u32,
+    deaths: u32,
+    ping: String,
 }
 
 enum State {
     Instructions,
     Playing,
     Dead,
+    GameOver,
 }
 
 /// Now we're getting into the actual game loop. The `MainState` is our game's "global" state, it
 /// keeps track of everything we need for actually running the game.
 ///
-/// Our game objects are simply a vector for each actor type, and we probably mingle gameplay-state
-/// (like score) and hardware-state (like `input`) a little more than we should, but for something
-/// this small it hardly matters.
+/// The game objects themselves live in the `World`; the `MainState` wraps it up with the things
+/// that need a real machine: the window, the sounds, the keyboard and the network.
 pub struct MainState {
-    player: Actor,
-    other_players: HashMap<String, Actor>,
-    shots: Vec<Actor>,
-    rocks: Vec<Actor>,
-    level: i32,
-    score: i32,
+    world: World,
     assets: Assets,
+    // The size of what we draw, in world units. The window is scaled to show all of it.
     screen_width: f32,
     screen_height: f32,
+    // The part of the world the window shows. It's bigger than the world itself when the
+    // window's shape doesn't match, and the spare room is blacked out.
+    view: graphics::Rect,
+    camera: Camera,
+    starfield: Starfield,
     input: InputState,
-    player_shot_timeout: f32,
     state: State,
     state_transition: f32,
-    hidpi_factor: f32,
-    tx: futures::sync::mpsc::UnboundedSender<HashMap<String, f64>>,
-    rx: mpsc::Receiver<HashMap<String, f64>>,
+    tick_rate: u32,
+    network: Network,
+    id: u32,
+    // The ids of everyone we've heard from, by address.
+    peer_ids: HashMap<String, u32>,
+    // The names and colours of the other players, by address.
+    profiles: HashMap<String, Profile>,
+    name: String,
+    color: graphics::Color,
+    // The colour the player asked for, if they did.
+    chosen_color: Option<u32>,
+    seq: u32,
+    // How each class of ship handles, for when the player switches between them.
+    flight: HashMap<ShipClass, FlightModel>,
+    net_stats: NetStats,
+    show_net_stats: bool,
+    show_radar: bool,
+    show_scoreboard: bool,
+    chat: Chat,
+    // What we're typing, while we're typing a chat message.
+    chat_entry: Option<String>,
+    // Set while the T that opened chat is held down, so neither it nor its repeats end up in the
+    // message.
+    chat_skip_char: bool,
+    // Set when Q went down while typing, so letting go of it after the chat box closes doesn't
+    // quit.
+    chat_held_q: bool,
+    radar_corner: Corner,
+    ping_timeout: f32,
 }
 
 impl MainState {
-    pub fn new(
-        ctx: &mut Context,
-        tx: futures::sync::mpsc::UnboundedSender<HashMap<String, f64>>,
-        rx: std::sync::mpsc::Receiver<HashMap<String, f64>>,
-        hidpi_factor: f32,
-    ) -> GameResult<MainState> {
+    pub fn new(ctx: &mut Context, network: Network, settings: Settings) -> GameResult<MainState> {
         let assets = Assets::new(ctx)?;
-        let player = Actor::create_player("self".to_string());
-        let rocks = create_rocks(5, player.pos, 100.0 * hidpi_factor, 250.0 * hidpi_factor);
-
-        let s = MainState {
-            player,
-            other_players: HashMap::new(),
-            shots: Vec::new(),
-            rocks,
-            level: 0,
-            score: 0,
+        let window_width = ctx.conf.window_mode.width;
+        let window_height = ctx.conf.window_mode.height;
+
+        let (world_width, world_height) = settings.world_size;
+        let mut world = World::new(world_width, world_height, settings.seed);
+        world.bounce = settings.bounce;
+        let class = ShipClass::default();
+        world.set_ship_class(class, settings.flight[&class]);
+        world.set_arena(settings.arena);
+
+        let id: u32 = rand::random();
+        let name = settings
+            .name
+            .unwrap_or_else(|| format!("Pilot {}", id % 1000));
+
+        let mut s = MainState {
+            world,
             assets,
-            screen_width: ctx.conf.window_mode.width,
-            screen_height: ctx.conf.window_mode.height,
+            screen_width: VIEW_WIDTH,
+            screen_height: VIEW_HEIGHT,
+            view: graphics::Rect::new(0.0, 0.0, VIEW_WIDTH, VIEW_HEIGHT),
+            camera: Camera::new(VIEW_WIDTH, VIEW_HEIGHT, world_width, world_height),
+            starfield: Starfield::new(settings.seed, VIEW_WIDTH, VIEW_HEIGHT),
             input: InputState::default(),
-            player_shot_timeout: 0.0,
             state_transition: 5.0,
             state: State::Instructions,
-            hidpi_factor,
-            tx,
-            rx,
+            tick_rate: settings.tick_rate,
+            network,
+            id,
+            peer_ids: HashMap::new(),
+            profiles: HashMap::new(),
+            name,
+            color: graphics::Color::from_rgb_u32(settings.color.unwrap_or(PLAYER_COLORS[0])),
+            chosen_color: settings.color,
+            seq: 0,
+            flight: settings.flight,
+            net_stats: NetStats::default(),
+            show_net_stats: false,
+            show_scoreboard: false,
+            chat: Chat::default(),
+            chat_entry: None,
+            chat_skip_char: false,
+            chat_held_q: false,
+            // There's no need for a radar when the whole world is on screen anyway.
+            show_radar: world_width > VIEW_WIDTH || world_height > VIEW_HEIGHT,
+            radar_corner: settings.radar_corner,
+            ping_timeout: 0.0,
         };
+        s.fit_to_window(ctx, window_width, window_height)?;
 
         Ok(s)
     }
 
-    fn reset_state(&mut self) {
-        let player = Actor::create_player("self".to_string());
-        let rocks = create_rocks(5, player.pos, 100.0, 250.0);
+    /// Plays the sounds for whatever just happened in the world.
+    fn play_sounds(&mut self, events: &[Event]) {
+        for event in events {
+            match event {
+                Event::ShotFired { pos, .. } => {
+                    let pos = world_to_audio_coords(&self.camera, *pos);
+                    self.assets.shot_sound.set_position(pos);
+                    let _ = self.assets.shot_sound.play();
+                }
+                Event::SaucerFired { pos, .. } => {
+                    let pos = world_to_audio_coords(&self.camera, *pos);
+                    self.assets.shot_sound.set_position(pos);
+                    let _ = self.assets.shot_sound.play();
+                }
+                Event::RockDestroyed { pos, .. } | Event::SaucerDestroyed { pos, .. } => {
+                    let pos = world_to_audio_coords(&self.camera, *pos);
+                    self.assets.hit_sound.set_position(pos);
+                    let _ = self.assets.hit_sound.play();
+                }
+                Event::RockSpawned { .. }
+                | Event::PlayerDied { .. }
+                | Event::PickupClaimed { .. }
+                | Event::RockHit { .. }
+                | Event::SaucerHit { .. } => {}
+            }
+        }
+    }
+
+    fn draw_ui(&mut self, ctx: &mut Context) -> GameResult {
+        let level_dest = Point2::new(10.0, 10.0);
+        let score_dest = Point2::new(140.0, 10.0);
+        let lives_dest = Point2::new(320.0, 10.0);
+        let weapon_dest = Point2::new(480.0, 10.0);
+
+        let level_str = format!("Level: {}", self.world.level);
+        let score_str = format!("Score: {}", self.world.score);
+        let lives_str = format!("Lives: {}", self.world.lives);
+        let weapon_str = format!("Weapon: {}", WEAPONS[self.world.player.weapon].name);
+
+        let level_display = graphics::Text::new((level_str, self.assets.font, 20.0));
+        let score_display = graphics::Text::new((score_str, self.assets.font, 20.0));
+        let lives_display = graphics::Text::new((lives_str, self.assets.font, 20.0));
+        let weapon_display = graphics::Text::new((weapon_str, self.assets.font, 20.0));
+
+        graphics::draw(ctx, &level_display, (level_dest, 0.0, graphics::WHITE))?;
+        graphics::draw(ctx, &score_display, (score_dest, 0.0, graphics::WHITE))?;
+        graphics::draw(ctx, &lives_display, (lives_dest, 0.0, graphics::WHITE))?;
+        graphics::draw(ctx, &weapon_display, (weapon_dest, 0.0, graphics::WHITE))?;
+
+        self.draw_network_status(ctx)?;
+        self.draw_leader(ctx)?;
+        self.draw_abilities(ctx)?;
+        self.draw_effects(ctx)?;
 
-        self.player = player;
-        self.shots = Vec::new();
-        self.rocks = rocks;
-        self.level = 0;
-        self.score = 0;
-        self.player_shot_timeout = 0.0;
+        if self.show_net_stats {
+            self.draw_net_stats(ctx)?;
+        }
+        if self.show_radar {
+            self.draw_radar(ctx)?;
+        }
+
+        Ok(())
     }
 
-    fn fire_player_shot(&mut self) {
-        self.player_shot_timeout = PLAYER_SHOT_TIME;
+    /// Draws a little map of the whole world, centred on the middle of the screen, with a box
+    /// around the part that's on screen. In a bigger world the camera follows our ship, so that's
+    /// where we are; in the classic world the camera never moves, and we're wherever we've flown.
+    fn draw_radar(&self, ctx: &mut Context) -> GameResult {
+        let camera = &self.camera;
+        let scale = RADAR_WIDTH / camera.world_width;
+        let (width, height) = (RADAR_WIDTH, camera.world_height * scale);
+        let x = match self.radar_corner {
+            Corner::TopLeft | Corner::BottomLeft => RADAR_MARGIN,
+            Corner::TopRight | Corner::BottomRight => self.screen_width - RADAR_MARGIN - width,
+        };
+        let y = match self.radar_corner {
+            Corner::TopLeft | Corner::TopRight => RADAR_MARGIN,
+            Corner::BottomLeft | Corner::BottomRight => self.screen_height - RADAR_MARGIN - height,
+        };
+        let center = Point2::new(x + width / 2.0, y + height / 2.0);
+        // Where something shows up on the radar, with Y flipped to point down the screen.
+        let blip = |pos: Point2| {
+            let offset = camera.offset(pos) * scale;
+            Point2::new(center.x + offset.x, center.y - offset.y)
+        };
 
-        let player = &self.player;
-        let mut shot = Actor::create_shot("self".to_string());
-        shot.pos = player.pos;
-        shot.facing = player.facing;
-        shot.velocity = player.velocity;
-        let direction = vec_from_angle(shot.facing);
-        shot.velocity.x += SHOT_SPEED * direction.x;
-        shot.velocity.y += SHOT_SPEED * direction.y;
+        let mut builder = graphics::MeshBuilder::new();
+        builder.rectangle(
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(x, y, width, height),
+            graphics::Color::new(0.0, 0.0, 0.0, 0.6),
+        );
+        builder.rectangle(
+            graphics::DrawMode::stroke(1.0),
+            graphics::Rect::new(x, y, width, height),
+            graphics::Color::new(0.5, 0.5, 0.5, 1.0),
+        );
+        let (view_width, view_height) = (camera.view_width * scale, camera.view_height * scale);
+        builder.rectangle(
+            graphics::DrawMode::stroke(1.0),
+            graphics::Rect::new(
+                center.x - view_width / 2.0,
+                center.y - view_height / 2.0,
+                view_width,
+                view_height,
+            ),
+            graphics::Color::new(0.3, 0.3, 0.3, 1.0),
+        );
+
+        let mut dot = |pos: Point2, size: f32, color: graphics::Color| {
+            builder.circle(graphics::DrawMode::fill(), blip(pos), size, 0.5, color);
+        };
+        for r in &self.world.rocks {
+            dot(r.pos, 2.0, graphics::Color::new(0.6, 0.6, 0.6, 1.0));
+        }
+        for s in &self.world.shots {
+            dot(s.pos, 1.0, self.color);
+        }
+        for s in &self.world.other_shots {
+            dot(s.pos, 1.0, self.player_color(&s.owner));
+        }
+        if let Some(saucer) = &self.world.saucer {
+            dot(saucer.pos, 2.5, graphics::Color::new(0.4, 1.0, 0.4, 1.0));
+        }
+        for (ip, p) in &self.world.other_players {
+            dot(p.pos, 3.0, self.player_color(ip));
+        }
+        dot(self.world.player.pos, 3.0, self.color);
 
-        self.shots.push(shot);
+        let mesh = builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, graphics::DrawParam::new())
+    }
 
-        let pos = world_to_audio_coords(self.screen_width, self.screen_height, player.pos);
-        self.assets.shot_sound.set_position(pos);
-        let _ = self.assets.shot_sound.play();
+    /// Everyone in the game, us included, best score first.
+    fn scoreboard(&mut self) -> Vec<ScoreRow> {
+        let ping = |rtt: Option<Duration>| {
+            rtt.map_or("-".to_string(), |rtt| format!("{}ms", rtt.as_millis()))
+        };
+        let mut rows = vec![ScoreRow {
+            name: self.name.clone(),
+            color: self.color,
+            score: self.world.score,
+            kills: self.world.kills,
+            deaths: self.world.deaths,
+            ping: ping(self.net_stats.average_rtt()),
+        }];
+
+        for profile in self.profiles.values() {
+            rows.push(ScoreRow {
+                name: profile.name.clone(),
+                color: profile.color,
+                score: profile.score,
+                kills: profile.kills,
+                deaths: profile.deaths,
+                ping: ping(profile.rtt),
+            });
+        }
+
+        rows.sort_by_key(|row| std::cmp::Reverse(row.score));
+        rows
     }
 
-    fn clear_dead_stuff(&mut self) {
-        self.shots.retain(|s| s.life > 0.0);
-        self.rocks.retain(|r| r.life > 0.0);
+    /// Says who's winning, once there's anyone to be winning against.
+    fn draw_leader(&mut self, ctx: &mut Context) -> GameResult {
+        if self.profiles.is_empty() {
+            return Ok(());
+        }
+
+        let leader = &self.scoreboard()[0];
+        let text = format!("Leader: {} ({})", leader.name, leader.score);
+        let display = graphics::Text::new((text, self.assets.font, 12.0));
+        graphics::draw(ctx, &display, (Point2::new(320.0, 35.0), 0.0, leader.color))?;
+
+        Ok(())
     }
 
-    fn handle_collisions(&mut self) {
-        for rock in &mut self.rocks {
-            let pdistance = rock.pos - self.player.pos;
-            if pdistance.norm() < (self.player.bbox_size + rock.bbox_size) {
-                self.player.life = 0.0;
+    /// Lists everyone's score, kills, deaths and ping while Tab is held down. A player's ping is
+    /// their average round trip to everyone else, as they measured it themselves.
+    fn draw_scoreboard(&mut self, ctx: &mut Context) -> GameResult {
+        let rows = self.scoreboard();
+        let columns = [0.0, 220.0, 300.0, 370.0, 440.0];
+        let (width, line_height) = (520.0, 22.0);
+        let height = line_height * (rows.len() + 1) as f32 + 20.0;
+        let x = (self.screen_width - width) / 2.0;
+        let y = (self.screen_height - height) / 2.0;
+
+        let background = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(x, y, width, height),
+            graphics::Color::new(0.0, 0.0, 0.0, 0.8),
+        )?;
+        graphics::draw(ctx, &background, graphics::DrawParam::new())?;
+
+        let header = ["Player", "Score", "Kills", "Deaths", "Ping"];
+        let mut lines = vec![(
+            header.iter().map(|h| h.to_string()).collect::<Vec<_>>(),
+            graphics::Color::new(0.6, 0.6, 0.6, 1.0),
+        )];
+        for row in rows {
+            let cells = vec![
+                row.name,
+                row.score.to_string(),
+                row.kills.to_string(),
+                row.deaths.to_string(),
+                row.ping,
+            ];
+            lines.push((cells, row.color));
+        }
+
+        for (i, (cells, color)) in lines.iter().enumerate() {
+            let line_y = y + 10.0 + line_height * i as f32;
+            for (cell, column) in cells.iter().zip(columns.iter()) {
+                let dest = Point2::new(x + 10.0 + column, line_y);
+                let display = graphics::Text::new((cell.as_str(), self.assets.font, 16.0));
+                graphics::draw(ctx, &display, (dest, 0.0, *color))?;
             }
-            for shot in &mut self.shots {
-                let distance = shot.pos - rock.pos;
-                if distance.norm() < (shot.bbox_size + rock.bbox_size) {
-                    shot.life = 0.0;
-                    rock.life = 0.0;
-                    self.score += 1;
+        }
 
-                    let pos =
-                        world_to_audio_coords(self.screen_width, self.screen_height, rock.pos);
-                    self.assets.shot_sound.set_position(pos);
-                    let _ = self.assets.hit_sound.play();
+        Ok(())
+    }
+
+    fn draw_net_stats(&mut self, ctx: &mut Context) -> GameResult {
+        let now = Instant::now();
+        let mut lines = vec![format!(
+            "{:<22}{:>6}{:>8}{:>7}{:>9}{:>9}",
+            "peer", "age", "rtt", "loss", "in B/s", "out B/s"
+        )];
+
+        for ip in self.world.other_players.keys() {
+            if let Some(stats) = self.net_stats.summary(ip, now) {
+                let rtt = match stats.rtt {
+                    Some(rtt) => format!("{}ms", rtt.as_millis()),
+                    None => "-".to_string(),
+                };
+                lines.push(format!(
+                    "{:<22}{:>5.1}s{:>8}{:>6.1}%{:>9.0}{:>9.0}",
+                    ip.trim_start_matches("ip-"),
+                    timer::duration_to_f64(stats.age),
+                    rtt,
+                    stats.loss * 100.0,
+                    stats.bytes_in,
+                    stats.bytes_out,
+                ));
+            }
+        }
+
+        let stats_dest = Point2::new(10.0, 55.0);
+        let stats_display = graphics::Text::new((lines.join("\n"), self.assets.font, 10.0));
+        graphics::draw(ctx, &stats_display, (stats_dest, 0.0, graphics::WHITE))?;
+
+        Ok(())
+    }
+
+    /// Shows whether hyperspace and the shield are ready to use, or how long until they are.
+    fn draw_abilities(&self, ctx: &mut Context) -> GameResult {
+        let player = &self.world.player;
+        let ability = |name: &str, cooldown: f32| {
+            if cooldown > 0.0 {
+                (
+                    format!("{}: {:.1}s", name, cooldown),
+                    graphics::Color::new(0.5, 0.5, 0.5, 1.0),
+                )
+            } else {
+                (format!("{}: ready", name), graphics::WHITE)
+            }
+        };
+        let shield = if player.shield > 0.0 {
+            (
+                format!("Shield [S]: up {:.1}s", player.shield),
+                graphics::Color::new(0.3, 0.7, 1.0, 1.0),
+            )
+        } else {
+            ability("Shield [S]", player.shield_cooldown)
+        };
+        let max_health = self.world.ship_class().health();
+        let hull = (
+            format!("Hull: {:.1}/{:.0}", player.health.max(0.0), max_health),
+            if player.health < max_health {
+                graphics::Color::new(1.0, 0.3, 0.3, 1.0)
+            } else {
+                graphics::WHITE
+            },
+        );
+        let abilities = [
+            hull,
+            ability("Hyperspace [H]", player.hyperspace_cooldown),
+            shield,
+        ];
+
+        let y = self.screen_height - 25.0;
+        for (i, (text, color)) in abilities.iter().enumerate() {
+            let dest = Point2::new(10.0 + 200.0 * i as f32, y);
+            let display = graphics::Text::new((text.as_str(), self.assets.font, 12.0));
+            graphics::draw(ctx, &display, (dest, 0.0, *color))?;
+        }
+
+        Ok(())
+    }
+
+    /// Lists the power-ups the player has running and how long each has left.
+    fn draw_effects(&self, ctx: &mut Context) -> GameResult {
+        let y = self.screen_height - 45.0;
+        let mut x = 10.0;
+        for kind in PickupKind::ALL.iter() {
+            if let Some(time) = self.world.effects.get(kind) {
+                let text = format!("{} {:.1}s", kind.name(), time);
+                let display = graphics::Text::new((text, self.assets.font, 12.0));
+                graphics::draw(ctx, &display, (Point2::new(x, y), 0.0, pickup_color(*kind)))?;
+                x += 200.0;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Draws the stars, obstacles and wormholes. They're drawn first so everything else flies
+    /// over the top of them.
+    fn draw_arena(&self, ctx: &mut Context) -> GameResult {
+        let arena = self.world.arena();
+        let to_screen = |pos: Point2| Actor::world_to_screen_coords(&self.camera, pos);
+        let circle = |ctx: &mut Context, mode, pos, radius, color| -> GameResult {
+            let mesh = graphics::Mesh::new_circle(ctx, mode, to_screen(pos), radius, 0.5, color)?;
+            graphics::draw(ctx, &mesh, graphics::DrawParam::new())
+        };
+
+        for star in &arena.stars {
+            let color = graphics::Color::new(1.0, 0.85, 0.3, 1.0);
+            circle(
+                ctx,
+                graphics::DrawMode::fill(),
+                star.pos,
+                star.radius,
+                color,
+            )?;
+        }
+        for obstacle in &arena.obstacles {
+            let color = graphics::Color::new(0.6, 0.6, 0.6, 1.0);
+            circle(
+                ctx,
+                graphics::DrawMode::stroke(2.0),
+                obstacle.pos,
+                obstacle.radius,
+                color,
+            )?;
+        }
+        for hole in &arena.wormholes {
+            let color = graphics::Color::new(0.7, 0.4, 1.0, 1.0);
+            circle(
+                ctx,
+                graphics::DrawMode::stroke(2.0),
+                hole.a,
+                hole.radius,
+                color,
+            )?;
+            circle(
+                ctx,
+                graphics::DrawMode::stroke(2.0),
+                hole.b,
+                hole.radius,
+                color,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// The colour another player's ship and shots are drawn in.
+    fn player_color(&self, ip: &str) -> graphics::Color {
+        self.profiles
+            .get(ip)
+            .map_or(UNKNOWN_PLAYER_COLOR, |profile| profile.color)
+    }
+
+    /// Labels each other ship with who's flying it and what gun they've got.
+    fn draw_name_tags(&self, ctx: &mut Context) -> GameResult {
+        for (ip, p) in &self.world.other_players {
+            let pos = Actor::world_to_screen_coords(&self.camera, p.pos);
+            let dest = pos + Vector2::new(-20.0, 16.0);
+            let name = self.profiles.get(ip).map_or("?", |profile| &profile.name);
+            let weapon = WEAPONS.get(p.weapon).map_or("?", |w| w.name);
+            let tag = format!("{}\n{}", name, weapon);
+            let display = graphics::Text::new((tag, self.assets.font, 10.0));
+            graphics::draw(ctx, &display, (dest, 0.0, self.player_color(ip)))?;
+        }
+
+        Ok(())
+    }
+
+    fn draw_network_status(&self, ctx: &mut Context) -> GameResult {
+        let status = self.network.status();
+        let (status_str, color) = match status {
+            ConnectionStatus::Connected => (format!("Net: {}", status), graphics::WHITE),
+            ConnectionStatus::Degraded => (
+                format!("Net: {} (R to reconnect)", status),
+                graphics::Color::new(1.0, 0.8, 0.0, 1.0),
+            ),
+            ConnectionStatus::Failed => (
+                format!("Net: {} (R to reconnect)", status),
+                graphics::Color::new(1.0, 0.0, 0.0, 1.0),
+            ),
+        };
+
+        let status_dest = Point2::new(10.0, 35.0);
+        let status_display = graphics::Text::new((status_str, self.assets.font, 12.0));
+        graphics::draw(ctx, &status_display, (status_dest, 0.0, color))?;
+
+        Ok(())
+    }
+
+    /// Stamps a message with who we are and where it falls in our sequence, then sends it.
+    fn send_message(&mut self, mut map: Message) {
+        map.insert("id".to_string(), self.id.into());
+        map.insert("seq".to_string(), self.seq.into());
+        self.seq = self.seq.wrapping_add(1);
+
+        self.net_stats
+            .sent(hash_map_codec::encoded_len(&map), Instant::now());
+        self.network.send(map);
+    }
+
+    /// Tells everyone else about the things that happened in our world that they need to copy.
+    fn send_events(&mut self, events: &[Event]) {
+        for event in events {
+            match event {
+                Event::ShotFired {
+                    pos,
+                    facing,
+                    velocity,
+                    weapon,
+                } => {
+                    let mut map = Message::new();
+                    map.insert("tag".to_string(), network::TAG_SHOT);
+                    map.insert("pos_x".to_string(), pos.x.into());
+                    map.insert("pos_y".to_string(), pos.y.into());
+                    map.insert("facing".to_string(), (*facing).into());
+                    map.insert("velocity_x".to_string(), velocity.x.into());
+                    map.insert("velocity_y".to_string(), velocity.y.into());
+                    map.insert("weapon".to_string(), self.world.player.weapon as f64);
+                    map.insert("projectiles".to_string(), weapon.projectiles.into());
+                    map.insert("spread".to_string(), weapon.spread.into());
+                    self.send_message(map);
                 }
+                Event::RockSpawned { id } => self.send_rock_spawn(*id),
+                Event::RockHit { id, damage } => {
+                    let mut map = Message::new();
+                    map.insert("tag".to_string(), network::TAG_ROCK_HIT);
+                    map.insert("rock".to_string(), (*id).into());
+                    map.insert("damage".to_string(), (*damage).into());
+                    self.send_message(map);
+                }
+                Event::SaucerFired { pos, velocity } => {
+                    let mut map = Message::new();
+                    map.insert("tag".to_string(), network::TAG_SAUCER_SHOT);
+                    map.insert("pos_x".to_string(), pos.x.into());
+                    map.insert("pos_y".to_string(), pos.y.into());
+                    map.insert("velocity_x".to_string(), velocity.x.into());
+                    map.insert("velocity_y".to_string(), velocity.y.into());
+                    self.send_message(map);
+                }
+                Event::SaucerHit { id, damage } => {
+                    let mut map = Message::new();
+                    map.insert("tag".to_string(), network::TAG_SAUCER_HIT);
+                    map.insert("saucer".to_string(), (*id).into());
+                    map.insert("damage".to_string(), (*damage).into());
+                    self.send_message(map);
+                }
+                Event::PickupClaimed { id, .. } => {
+                    let mut map = Message::new();
+                    map.insert("tag".to_string(), network::TAG_PICKUP_CLAIM);
+                    map.insert("pickup".to_string(), (*id).into());
+                    self.send_message(map);
+                }
+                _ => {}
             }
         }
     }
 
-    fn check_for_level_respawn(&mut self) {
-        if self.rocks.is_empty() {
-            self.level += 1;
-            let r = create_rocks(self.level + 5, self.player.pos, 100.0, 250.0);
-            self.rocks.extend(r);
+    /// Tells everyone about one of our rocks. The id doubles as the seed for the rock's outline,
+    /// so that's all they need to draw it just like we do. The seed and level go along too, so
+    /// anyone still among other rocks knows to drop them for ours.
+    fn send_rock_spawn(&mut self, id: u32) {
+        let rock = match self.world.rocks.iter().find(|r| r.id == id) {
+            Some(rock) => rock,
+            None => return,
+        };
+        let size = match rock.tag {
+            ActorType::Rock(size) => size,
+            _ => return,
+        };
+
+        let mut map = Message::new();
+        map.insert("tag".to_string(), network::TAG_ROCK_SPAWN);
+        map.insert("rock".to_string(), id.into());
+        map.insert("size".to_string(), size.to_f64());
+        map.insert("pos_x".to_string(), rock.pos.x.into());
+        map.insert("pos_y".to_string(), rock.pos.y.into());
+        map.insert("velocity_x".to_string(), rock.velocity.x.into());
+        map.insert("velocity_y".to_string(), rock.velocity.y.into());
+        map.insert("health".to_string(), rock.health.into());
+        insert_rock_field(&mut map, self.world.seed(), self.world.level);
+        self.send_message(map);
+    }
+
+    /// Tells everyone about all of the rocks we have right now, if the rocks are ours to tell
+    /// them about.
+    fn announce_rocks(&mut self) {
+        if !self.in_charge() {
+            return;
+        }
+
+        let ids: Vec<u32> = self.world.rocks.iter().map(|r| r.id).collect();
+        for id in ids {
+            self.send_rock_spawn(id);
         }
     }
 
-    fn draw_ui(&mut self, ctx: &mut Context) -> GameResult {
-        let level_dest = Point2::new(self.scaled_size(10.0), self.scaled_size(10.0));
-        let score_dest = Point2::new(self.scaled_size(140.0), self.scaled_size(10.0));
+    /// Tells everyone where the saucer we're flying is.
+    fn send_saucer(&mut self) {
+        let saucer = match &self.world.saucer {
+            Some(saucer) => saucer,
+            None => return,
+        };
+        let size = match saucer.tag {
+            ActorType::Saucer(size) => size,
+            _ => return,
+        };
 
-        let level_str = format!("Level: {}", self.level);
-        let score_str = format!("Score: {}", self.score);
+        let mut map = Message::new();
+        map.insert("tag".to_string(), network::TAG_SAUCER);
+        map.insert("saucer".to_string(), saucer.id.into());
+        map.insert("size".to_string(), size.to_f64());
+        map.insert("pos_x".to_string(), saucer.pos.x.into());
+        map.insert("pos_y".to_string(), saucer.pos.y.into());
+        map.insert("velocity_x".to_string(), saucer.velocity.x.into());
+        map.insert("velocity_y".to_string(), saucer.velocity.y.into());
+        map.insert("health".to_string(), saucer.health.into());
+        map.insert("flash".to_string(), saucer.flash.into());
+        self.send_message(map);
+    }
 
-        let level_display =
-            graphics::Text::new((level_str, self.assets.font, self.scaled_size(20.0)));
-        let score_display =
-            graphics::Text::new((score_str, self.assets.font, self.scaled_size(20.0)));
+    /// Whether we're on a screen where the player can pick which ship to fly next.
+    fn choosing_class(&self) -> bool {
+        matches!(self.state, State::Instructions | State::GameOver)
+    }
 
-        graphics::draw(ctx, &level_display, (level_dest, 0.0, graphics::WHITE))?;
-        graphics::draw(ctx, &score_display, (score_dest, 0.0, graphics::WHITE))?;
+    /// Moves `step` places along the list of ship classes, wrapping around at either end.
+    fn cycle_class(&mut self, step: isize) {
+        let classes = &ShipClass::ALL;
+        let current = self.world.ship_class();
+        let index = classes.iter().position(|&c| c == current).unwrap_or(0) as isize;
+        let next = classes[(index + step).rem_euclid(classes.len() as isize) as usize];
+        self.world.set_ship_class(next, self.flight[&next]);
+
+        // Give the player time to read about their pick before the game starts without them.
+        if let State::Instructions = self.state {
+            self.state_transition = 5.0;
+        }
+    }
+
+    /// The id of whoever flies the saucers and runs the field of rocks: the lowest, so that
+    /// there's only ever one saucer and one set of rocks however many of us are playing.
+    fn authority(&self) -> u32 {
+        self.peer_ids
+            .values()
+            .fold(self.id, |lowest, &id| lowest.min(id))
+    }
+
+    fn in_charge(&self) -> bool {
+        self.authority() == self.id
+    }
+
+    /// Sends what's been typed into the chat box to everyone, and puts it in our own log.
+    fn submit_chat(&mut self) {
+        let text = match self.chat_entry.take() {
+            Some(text) => text,
+            None => return,
+        };
+
+        let peers = self.peer_ids.values().cloned().collect::<Vec<_>>();
+        match self.chat.send(&text, peers) {
+            Some((seq, text)) => {
+                self.chat
+                    .push_line(chat::Line::new(&self.name, &text, self.color));
+                self.send_chat(seq, &text);
+            }
+            None if !chat::clean(&text).is_empty() => {
+                let grey = graphics::Color::new(0.6, 0.6, 0.6, 1.0);
+                self.chat.push_line(chat::Line::new(
+                    "",
+                    "(slow down, you're chatting too fast)",
+                    grey,
+                ));
+            }
+            None => {}
+        }
+    }
+
+    fn send_chat(&mut self, seq: u32, text: &str) {
+        let mut map = Message::new();
+        map.insert("tag".to_string(), network::TAG_CHAT);
+        map.insert("chat".to_string(), seq.into());
+        map.insert(format!("name-{}", self.name), 0.0);
+        map.insert(format!("text-{}", text), 0.0);
+        map.insert("color".to_string(), self.color.to_rgb_u32().into());
+        self.send_message(map);
+    }
+
+    /// Draws the recent chat, fading out as it gets old, and the message being typed.
+    fn draw_chat(&self, ctx: &mut Context) -> GameResult {
+        let x = 10.0;
+        let mut y = self.screen_height - 70.0;
+
+        if let Some(entry) = &self.chat_entry {
+            let text = format!("Say: {}_", entry);
+            let display = graphics::Text::new((text, self.assets.font, 14.0));
+            graphics::draw(ctx, &display, (Point2::new(x, y), 0.0, graphics::WHITE))?;
+        }
+
+        for line in self.chat.lines().collect::<Vec<_>>().into_iter().rev() {
+            y -= 18.0;
+            let text = if line.name.is_empty() {
+                line.text.clone()
+            } else {
+                format!("{}: {}", line.name, line.text)
+            };
+            let mut color = line.color;
+            color.a = line.alpha();
+            let display = graphics::Text::new((text, self.assets.font, 14.0));
+            graphics::draw(ctx, &display, (Point2::new(x, y), 0.0, color))?;
+        }
 
         Ok(())
     }
 
+    fn send_ping(&mut self) {
+        let ping = self.net_stats.start_ping(Instant::now());
+
+        let mut map = Message::new();
+        map.insert("tag".to_string(), network::TAG_PING);
+        map.insert("ping".to_string(), ping.into());
+        self.send_message(map);
+    }
+
+    fn handle_message(&mut self, mut map: Message) {
+        let ip = match map.keys().find(|&k| k.starts_with("ip-")) {
+            Some(ip) => ip.to_string(),
+            None => return,
+        };
+        map.remove(&ip);
+
+        let now = Instant::now();
+        let sender = field(&map, "id").map(|id| id as u32);
+        // Everything we send comes back to us too, but that says nothing about our links.
+        if sender != Some(self.id) {
+            let seq = field(&map, "seq").map(|seq| seq as u32);
+            self.net_stats
+                .received(&ip, seq, hash_map_codec::encoded_len(&map), now);
+        }
+
+        if let Some(id) = sender.filter(|&id| id != self.id) {
+            // Somebody new needs to hear about the rocks they've missed.
+            if self.peer_ids.insert(ip.clone(), id).is_none() {
+                self.announce_rocks();
+                self.pick_color();
+            }
+        }
+
+        // A message that's missing something we need is from someone speaking a different version
+        // of the protocol, or from a stranger on the same group; nothing we can do with it.
+        self.handle_tagged(ip, sender, &map, now);
+    }
+
+    /// Acts on a message from `ip`, or gives up with `None` as soon as it finds something missing.
+    fn handle_tagged(
+        &mut self,
+        ip: String,
+        sender: Option<u32>,
+        map: &Message,
+        now: Instant,
+    ) -> Option<()> {
+        let from_peer = matches!(sender, Some(id) if id != self.id);
+        let from_authority = from_peer && sender == Some(self.authority());
+
+        match field(map, "tag")? {
+            // Our own updates come back to us too, but we're not one of the other players.
+            tag if tag == network::TAG_PLAYER && from_peer => {
+                let name = message_text(map, "name-")
+                    .unwrap_or("?")
+                    .chars()
+                    .take(MAX_NAME_LEN)
+                    .collect();
+                let profile = Profile {
+                    name,
+                    color: graphics::Color::from_rgb_u32(field(map, "color")? as u32),
+                    score: field(map, "score")? as i32,
+                    kills: field(map, "kills")? as u32,
+                    deaths: field(map, "deaths")? as u32,
+                    // Anything negative means they haven't heard back from anyone yet.
+                    rtt: Some(field(map, "rtt")?)
+                        .filter(|rtt| *rtt >= 0.0)
+                        .map(|rtt| Duration::from_millis(rtt as u64)),
+                };
+                let class = ShipClass::from_f64(field(map, "class")?).unwrap_or_default();
+                let pos = Point2::new(field(map, "pos_x")? as f32, field(map, "pos_y")? as f32);
+                let velocity = Vector2::new(
+                    field(map, "velocity_x")? as f32,
+                    field(map, "velocity_y")? as f32,
+                );
+                let facing = field(map, "facing")? as f32;
+                let ang_vel = field(map, "ang_vel")? as f32;
+                let invulnerable = field(map, "invulnerable")? as f32;
+                let shield = field(map, "shield")? as f32;
+                let shield_cooldown = field(map, "shield_cooldown")? as f32;
+                let hyperspace_cooldown = field(map, "hyperspace_cooldown")? as f32;
+                let weapon = field(map, "weapon")? as usize;
+                let health = field(map, "health")? as f32;
+                let flash = field(map, "flash")? as f32;
+
+                // Whoever's in charge decides which rocks we're all playing among, whether they
+                // bounce off each other, and how big the world they wrap round is, since we all
+                // move them ourselves.
+                if from_authority {
+                    let (seed, level) = rock_field(map)?;
+                    let width = field(map, "world_width")? as f32;
+                    let height = field(map, "world_height")? as f32;
+                    // The same goes for sizes as for `--world-size`.
+                    if width < VIEW_WIDTH || height < VIEW_HEIGHT {
+                        return None;
+                    }
+                    self.world.follow_field(seed, level);
+                    self.world.bounce = field(map, "bounce")? != 0.0;
+                    if (width, height) != (self.world.width, self.world.height) {
+                        self.world.set_size(width, height);
+                        self.camera = Camera::new(VIEW_WIDTH, VIEW_HEIGHT, width, height);
+                    }
+                }
+
+                self.profiles.insert(ip.clone(), profile);
+                let other_player = self
+                    .world
+                    .other_players
+                    .entry(ip.clone())
+                    .or_insert_with(|| Actor::create_player(ip.clone(), class));
+                // They've picked a different ship since we last saw them.
+                if other_player.tag != ActorType::Player(class) {
+                    *other_player = Actor::create_player(ip, class);
+                }
+
+                other_player.pos = pos;
+                other_player.facing = facing;
+                other_player.velocity = velocity;
+                other_player.ang_vel = ang_vel;
+                other_player.invulnerable = invulnerable;
+                other_player.shield = shield;
+                other_player.shield_cooldown = shield_cooldown;
+                other_player.hyperspace_cooldown = hyperspace_cooldown;
+                other_player.weapon = weapon;
+                other_player.health = health;
+                other_player.flash = flash;
+            }
+            tag if tag == network::TAG_GOODBYE => self.forget_peer(&ip),
+            // Chat is sent until it's acknowledged, so we might see a message more than once.
+            // We acknowledge every copy in case our earlier acknowledgements went missing.
+            tag if tag == network::TAG_CHAT && from_peer => {
+                let sender = sender?;
+                let seq = field(map, "chat")? as u32;
+                let name = chat::clean(message_text(map, "name-").unwrap_or("?"))
+                    .chars()
+                    .take(MAX_NAME_LEN)
+                    .collect::<String>();
+                let text = message_text(map, "text-").unwrap_or("");
+                let color = graphics::Color::from_rgb_u32(field(map, "color")? as u32);
+                self.chat.receive(sender, seq, &name, text, color);
+
+                let mut ack = Message::new();
+                ack.insert("tag".to_string(), network::TAG_CHAT_ACK);
+                ack.insert("to".to_string(), sender.into());
+                ack.insert("chat".to_string(), seq.into());
+                self.send_message(ack);
+            }
+            tag if tag == network::TAG_CHAT_ACK && field(map, "to")? as u32 == self.id => {
+                self.chat.acknowledge(field(map, "chat")? as u32, sender?);
+            }
+            // Somebody shot a rock. We're all playing among the same rocks, so we have it too, and
+            // if that finishes it off we break it up the same way they did.
+            tag if tag == network::TAG_ROCK_HIT && from_peer => {
+                let rock = field(map, "rock")? as u32;
+                let damage = field(map, "damage")? as f32;
+                if let Some(event) = self.world.damage_rock(rock, damage) {
+                    self.play_sounds(&[event]);
+                }
+            }
+            tag if tag == network::TAG_ROCK_SPAWN && from_authority => {
+                let (seed, level) = rock_field(map)?;
+                let size = RockSize::from_f64(field(map, "size")?)?;
+                let mut rock = Actor::create_rock(ip, size, field(map, "rock")? as u32);
+                rock.pos = Point2::new(field(map, "pos_x")? as f32, field(map, "pos_y")? as f32);
+                rock.velocity = Vector2::new(
+                    field(map, "velocity_x")? as f32,
+                    field(map, "velocity_y")? as f32,
+                );
+                rock.health = field(map, "health")? as f32;
+                self.world.follow_field(seed, level);
+                self.world.add_rock(rock);
+            }
+            // Only whoever's in charge flies the saucer. A peer that hasn't yet heard it's been
+            // handed over would otherwise have us flying two of them.
+            tag if tag == network::TAG_SAUCER && from_authority => {
+                let size = SaucerSize::from_f64(field(map, "size")?)?;
+                let mut saucer = Actor::create_saucer(ip, size, field(map, "saucer")? as u32);
+                saucer.pos = Point2::new(field(map, "pos_x")? as f32, field(map, "pos_y")? as f32);
+                saucer.velocity = Vector2::new(
+                    field(map, "velocity_x")? as f32,
+                    field(map, "velocity_y")? as f32,
+                );
+                saucer.health = field(map, "health")? as f32;
+                saucer.flash = field(map, "flash")? as f32;
+                self.world.sync_saucer(saucer);
+            }
+            tag if tag == network::TAG_SAUCER_SHOT && from_authority => {
+                let pos = Point2::new(field(map, "pos_x")? as f32, field(map, "pos_y")? as f32);
+                let velocity = Vector2::new(
+                    field(map, "velocity_x")? as f32,
+                    field(map, "velocity_y")? as f32,
+                );
+                self.world.add_saucer_shot(pos, velocity);
+                self.play_sounds(&[Event::SaucerFired { pos, velocity }]);
+            }
+            tag if tag == network::TAG_SAUCER_HIT && from_peer => {
+                let saucer = field(map, "saucer")? as u32;
+                let damage = field(map, "damage")? as f32;
+                if let Some(event) = self.world.damage_saucer(saucer, damage) {
+                    self.play_sounds(&[event]);
+                }
+            }
+            // Somebody fired. The volley is rebuilt from their weapon, with the shot count and
+            // spread sent along since power-ups can change those. Anything bigger than a volley
+            // can be is bogus, and we don't want to make however many shots it asks for.
+            tag if tag == network::TAG_SHOT && from_peer => {
+                let mut weapon = *WEAPONS.get(field(map, "weapon")? as usize)?;
+                weapon.projectiles = field(map, "projectiles")
+                    .filter(|n| *n >= 1.0 && *n <= f64::from(MAX_PROJECTILES))?
+                    as u32;
+                weapon.spread = field(map, "spread")
+                    .filter(|s| *s >= 0.0 && *s <= f64::from(MAX_SPREAD))?
+                    as f32;
+
+                let pos = Point2::new(field(map, "pos_x")? as f32, field(map, "pos_y")? as f32);
+                let velocity = Vector2::new(
+                    field(map, "velocity_x")? as f32,
+                    field(map, "velocity_y")? as f32,
+                );
+                let facing = field(map, "facing")? as f32;
+                self.world
+                    .add_other_volley(&ip, pos, facing, velocity, &weapon);
+            }
+            // If we took the same pickup at the same moment, whoever has the lowest id keeps it.
+            tag if tag == network::TAG_PICKUP_CLAIM && from_peer => {
+                let pickup = field(map, "pickup")? as u32;
+                self.world.remove_pickup(pickup);
+                if sender? < self.id {
+                    self.world.lose_pickup(pickup);
+                }
+            }
+            tag if tag == network::TAG_PING && from_peer => {
+                let mut pong = Message::new();
+                pong.insert("tag".to_string(), network::TAG_PONG);
+                pong.insert("to".to_string(), sender?.into());
+                pong.insert("ping".to_string(), field(map, "ping")?);
+                self.send_message(pong);
+            }
+            tag if tag == network::TAG_PONG && from_peer && field(map, "to")? as u32 == self.id => {
+                let ping = field(map, "ping")? as u32;
+                self.net_stats.pong(&ip, ping, now);
+            }
+            // Someone speaking a different version of the protocol; nothing we can do with it.
+            _ => {}
+        }
+
+        Some(())
+    }
+
+    /// Forgets everything about a peer that has left.
+    fn forget_peer(&mut self, ip: &str) {
+        self.world.other_players.remove(ip);
+        self.net_stats.forget(ip);
+        if let Some(id) = self.peer_ids.remove(ip) {
+            self.chat.forget_peer(id);
+        }
+        self.profiles.remove(ip);
+        self.pick_color();
+    }
+
+    /// Takes the colour that goes with how many players have a lower id than us, unless we picked
+    /// our own. Everyone ranks the same way, so nobody looks alike until the colours run out.
+    fn pick_color(&mut self) {
+        if self.chosen_color.is_none() {
+            let rank = self.peer_ids.values().filter(|&&id| id < self.id).count();
+            let color = PLAYER_COLORS[rank % PLAYER_COLORS.len()];
+            self.color = graphics::Color::from_rgb_u32(color);
+        }
+    }
+
+    /// Says goodbye to everyone and closes the socket before we exit.
+    fn quit(&mut self, ctx: &mut Context) {
+        self.network.shutdown();
+        ggez::quit(ctx);
+    }
+
     fn draw_instructions(&self, ctx: &mut Context) -> GameResult {
         let instructions = graphics::Text::new((
-            String::from("\n   !!! Welcome to ASTROBLASTO!!!\n\n\nHow to play:\nL/R arrow keys rotate your ship,\nup thrusts, space bar fires"),
+            format!("\n   !!! Welcome to ASTROBLASTO!!!\n\n\nHow to play:\nL/R arrow keys rotate your ship,\nup thrusts, down reverses,\nA/D strafe, space bar fires,\nH jumps to hyperspace,\nS raises your shield,\n1-4 pick your weapon,\nM toggles radar, Tab shows scores,\nT or Enter to chat\n\nShip (L/R to change): {}", self.world.ship_class().name()),
             self.assets.font,
-            self.scaled_size(32.0),
+            32.0,
         ));
 
         graphics::draw(
@@ -332,18 +1202,64 @@ impl MainState {
     }
 
     fn draw_death_screen(&self, ctx: &mut Context) -> GameResult {
-        let text = graphics::Text::new((String::from("You died!"), self.assets.font, 32.0));
+        let lives = match self.world.lives {
+            1 => String::from("1 ship left"),
+            n => format!("{} ships left", n),
+        };
+        let text = graphics::Text::new((format!("You died!\n\n{}", lives), self.assets.font, 32.0));
         graphics::draw(ctx, &text, (Point2::new(10.0, 10.0), 0.0, graphics::WHITE))?;
         Ok(())
     }
 
-    /// Takes a given size and scales it based on the window dimensions
-    fn scaled_size(&self, size: f32) -> f32 {
-        if self.screen_width > 800.0 {
-            size * 2.0
-        } else {
-            size
+    fn draw_game_over(&self, ctx: &mut Context) -> GameResult {
+        let summary = format!(
+            "\n   GAME OVER\n\n\nFinal score: {}\nReached level: {}\n\nShip (L/R to change): {}\nPress space to play again",
+            self.world.score,
+            self.world.level,
+            self.world.ship_class().name()
+        );
+        let text = graphics::Text::new((summary, self.assets.font, 32.0));
+        graphics::draw(ctx, &text, (Point2::new(50.0, 50.0), 0.0, graphics::WHITE))?;
+        Ok(())
+    }
+
+    /// Scales the world to fit a window of the given size, as big as it'll go without stretching
+    /// and centred in whatever room is left over.
+    fn fit_to_window(&mut self, ctx: &mut Context, width: f32, height: f32) -> GameResult {
+        let scale = (width / self.screen_width).min(height / self.screen_height);
+        let (view_width, view_height) = (width / scale, height / scale);
+        self.view = graphics::Rect::new(
+            (self.screen_width - view_width) / 2.0,
+            (self.screen_height - view_height) / 2.0,
+            view_width,
+            view_height,
+        );
+        graphics::set_screen_coordinates(ctx, self.view)
+    }
+
+    /// Blacks out any part of the window outside the world, hiding the bits of actors that are
+    /// drawn hanging over the edges.
+    fn draw_letterbox(&self, ctx: &mut Context) -> GameResult {
+        let view = self.view;
+        let (side, top) = (-view.x, -view.y);
+        let bars = [
+            graphics::Rect::new(view.x, view.y, side, view.h),
+            graphics::Rect::new(self.screen_width, view.y, side, view.h),
+            graphics::Rect::new(view.x, view.y, view.w, top),
+            graphics::Rect::new(view.x, self.screen_height, view.w, top),
+        ];
+
+        for bar in bars.iter().filter(|b| b.w > 0.0 && b.h > 0.0) {
+            let mesh = graphics::Mesh::new_rectangle(
+                ctx,
+                graphics::DrawMode::fill(),
+                *bar,
+                graphics::BLACK,
+            )?;
+            graphics::draw(ctx, &mesh, graphics::DrawParam::new())?;
         }
+
+        Ok(())
     }
 }
 
@@ -351,122 +1267,139 @@ impl MainState {
 /// callbacks for updating and drawing our game, as well as handling input events.
 impl EventHandler for MainState {
     fn update(&mut self, ctx: &mut Context) -> GameResult {
-        const DESIRED_FPS: u32 = 60;
-
-        while timer::check_update_time(ctx, DESIRED_FPS) {
-            let delta = 1.0 / (DESIRED_FPS as f32);
-
-            match self.rx.try_recv() {
-                Ok(map) => {
-                    let ip = map
-                        .keys()
-                        .find(|&k| k.starts_with("ip-"))
-                        .expect("no ip key")
-                        .to_string();
-
-                    // Player
-                    if map[&"tag".to_string()] == 1.0 {
-                        let other_player = self
-                            .other_players
-                            .entry(ip.clone())
-                            .or_insert(Actor::create_player(ip));
-
-                        other_player.pos.x = map[&"pos_x".to_string()] as f32;
-                        other_player.pos.y = map[&"pos_y".to_string()] as f32;
-                        other_player.facing = map[&"facing".to_string()] as f32;
-                        other_player.velocity.x = map[&"velocity_x".to_string()] as f32;
-                        other_player.velocity.y = map[&"velocity_y".to_string()] as f32;
-                        other_player.ang_vel = map[&"ang_vel".to_string()] as f32;
-                    } else {
-                        unreachable!();
-                    }
+        while timer::check_update_time(ctx, self.tick_rate) {
+            let delta = 1.0 / (self.tick_rate as f32);
+
+            while let Some(map) = self.network.try_recv() {
+                self.handle_message(map);
+            }
+
+            self.ping_timeout -= delta;
+            if self.ping_timeout < 0.0 {
+                self.ping_timeout = PING_TIME;
+                self.send_ping();
+
+                // Peers that crashed or dropped off the network never say goodbye, and would
+                // otherwise stay in charge of the saucers and rocks forever.
+                for ip in self.net_stats.silent_peers(Instant::now(), PEER_TIMEOUT) {
+                    self.forget_peer(&ip);
                 }
-                _ => {}
             }
 
+            for (seq, text) in self.chat.update(delta) {
+                self.send_chat(seq, &text);
+            }
+
+            // The rocks, saucers and pickups are shared, so they carry on for everyone else
+            // whether or not we're flying just now, and they have to carry on here too.
+            let in_charge = self.in_charge();
+            self.world.simulate_saucers = in_charge;
+            self.world.advance_levels = in_charge;
+            let events = match self.state {
+                State::Playing => self.world.step(&self.input, delta),
+                _ => self.world.step_field(delta),
+            };
+            self.play_sounds(&events);
+            self.send_events(&events);
+
             match self.state {
                 State::Instructions => {
-                    if self.state_transition >= 0.0 {
+                    if self.input.fire {
+                        self.input.fire = false;
+                        self.state = State::Playing;
+                        self.announce_rocks();
+                    } else if self.state_transition >= 0.0 {
                         self.state_transition -= delta;
                     } else {
                         self.state = State::Playing;
-                    }
-
-                    if self.input.fire {
-                        self.state = State::Playing;
-                        self.input.fire = false;
+                        self.announce_rocks();
                     }
                 }
                 State::Playing => {
-                    // Update the player state based on the user input.
-                    player_handle_input(&mut self.player, &self.input, delta);
-                    self.player_shot_timeout -= delta;
-                    if self.input.fire && self.player_shot_timeout < 0.0 {
-                        self.fire_player_shot();
-                    }
-
-                    // Update the physics for all actors.
-                    update_actor_position(&mut self.player, delta);
-                    clamp_actor_velocity(&mut self.player);
-                    wrap_actor_position(
-                        &mut self.player,
-                        self.screen_width as f32,
-                        self.screen_height as f32,
-                    );
-
-                    for act in &mut self.shots {
-                        update_actor_position(act, delta);
-                        wrap_actor_position(
-                            act,
-                            self.screen_width as f32,
-                            self.screen_height as f32,
-                        );
-                        handle_timed_life(act, delta);
-                    }
-
-                    for act in &mut self.rocks {
-                        update_actor_position(act, delta);
-                        wrap_actor_position(
-                            act,
-                            self.screen_width as f32,
-                            self.screen_height as f32,
-                        );
-                    }
-
-                    // Handle the results of things moving:
-                    //
-                    // collision detection, object death, and if we have killed all the rocks in
-                    // the level, spawn more of them.
-                    self.handle_collisions();
-                    self.clear_dead_stuff();
-                    self.check_for_level_respawn();
-
-                    // Finally we check for our end state.
-                    if self.player.life <= 0.0 {
-                        self.state = State::Dead;
-                        self.state_transition = 5.0;
-                        self.reset_state();
+                    if events.iter().any(|e| matches!(e, Event::PlayerDied { .. })) {
+                        if self.world.game_over() {
+                            // Starting again takes a fresh press of fire, not one that's still
+                            // held down from the game that's just ended.
+                            self.input.fire = false;
+                            self.state = State::GameOver;
+                        } else {
+                            self.state = State::Dead;
+                            self.state_transition = RESPAWN_TIME;
+                        }
                     }
                 }
                 State::Dead => {
                     if self.state_transition >= 0.0 {
                         self.state_transition -= delta;
                     } else {
+                        self.world.respawn();
                         self.state = State::Playing;
                     }
                 }
+                State::GameOver => {
+                    if self.input.fire {
+                        self.input.fire = false;
+                        self.world.reset();
+                        // On our own there's nobody to share the rocks with, so they start over
+                        // too.
+                        if self.peer_ids.is_empty() {
+                            self.world.set_field(self.world.seed(), 0);
+                        }
+                        self.state = State::Playing;
+                        self.announce_rocks();
+                    }
+                }
+            }
+            self.camera.follow(self.world.player.pos);
+
+            let mut map = Message::new();
+            map.insert("pos_x".to_string(), self.world.player.pos.x.into());
+            map.insert("pos_y".to_string(), self.world.player.pos.y.into());
+            map.insert("facing".to_string(), self.world.player.facing.into());
+            map.insert(
+                "velocity_x".to_string(),
+                self.world.player.velocity.x.into(),
+            );
+            map.insert(
+                "velocity_y".to_string(),
+                self.world.player.velocity.y.into(),
+            );
+            map.insert("ang_vel".to_string(), self.world.player.ang_vel.into());
+            map.insert(
+                "invulnerable".to_string(),
+                self.world.player.invulnerable.into(),
+            );
+            map.insert("shield".to_string(), self.world.player.shield.into());
+            map.insert("weapon".to_string(), self.world.player.weapon as f64);
+            map.insert("class".to_string(), self.world.ship_class().to_f64());
+            map.insert(format!("name-{}", self.name), 0.0);
+            map.insert("color".to_string(), self.color.to_rgb_u32().into());
+            map.insert("score".to_string(), self.world.score.into());
+            map.insert("kills".to_string(), self.world.kills.into());
+            map.insert("deaths".to_string(), self.world.deaths.into());
+            let rtt = self.net_stats.average_rtt();
+            map.insert("rtt".to_string(), rtt.map_or(-1.0, |rtt| rtt.as_millis() as f64));
+            insert_rock_field(&mut map, self.world.seed(), self.world.level);
+            map.insert("bounce".to_string(), f64::from(u8::from(self.world.bounce)));
+            map.insert("world_width".to_string(), self.world.width.into());
+            map.insert("world_height".to_string(), self.world.height.into());
+            map.insert("health".to_string(), self.world.player.health.into());
+            map.insert("flash".to_string(), self.world.player.flash.into());
+            map.insert(
+                "shield_cooldown".to_string(),
+                self.world.player.shield_cooldown.into(),
+            );
+            map.insert(
+                "hyperspace_cooldown".to_string(),
+                self.world.player.hyperspace_cooldown.into(),
+            );
+            map.insert("tag".to_string(), network::TAG_PLAYER);
+
+            self.send_message(map);
+
+            if self.world.simulate_saucers {
+                self.send_saucer();
             }
-
-            let mut map: HashMap<String, f64> = HashMap::new();
-            map.insert("pos_x".to_string(), self.player.pos.x.into());
-            map.insert("pos_y".to_string(), self.player.pos.y.into());
-            map.insert("facing".to_string(), self.player.facing.into());
-            map.insert("velocity_x".to_string(), self.player.velocity.x.into());
-            map.insert("velocity_y".to_string(), self.player.velocity.y.into());
-            map.insert("ang_vel".to_string(), self.player.ang_vel.into());
-            map.insert("tag".to_string(), 1.0);
-
-            self.tx.unbounded_send(map).expect("unable to send");
         }
 
         Ok(())
@@ -482,34 +1415,67 @@ impl EventHandler for MainState {
             }
             State::Playing => {
                 // Loop over all objects drawing them.
-                let coords = (self.screen_width, self.screen_height);
-
-                let p = &self.player;
-                p.draw_actor(ctx, coords, self.hidpi_factor, graphics::WHITE)?;
-
-                for (_, p) in &self.other_players {
-                    p.draw_actor(
-                        ctx,
-                        coords,
-                        self.hidpi_factor,
-                        graphics::Color::new(1.0, 0.0, 0.0, 1.0),
-                    )?;
+                let camera = &self.camera;
+
+                // Ships that can't be hurt yet blink so everyone knows.
+                let visible = |p: &Actor| (p.invulnerable * BLINK_RATE) as i32 % 2 == 0;
+
+                self.starfield.draw(ctx, &self.camera)?;
+                self.draw_arena(ctx)?;
+
+                let p = &self.world.player;
+                if visible(p) {
+                    p.draw_actor(ctx, camera, self.color)?;
                 }
 
-                for s in &self.shots {
-                    s.draw_actor(ctx, coords, self.hidpi_factor, graphics::WHITE)?;
+                for (ip, p) in &self.world.other_players {
+                    if visible(p) {
+                        p.draw_actor(ctx, camera, self.player_color(ip))?;
+                    }
+                }
+
+                for s in &self.world.shots {
+                    s.draw_actor(ctx, camera, self.color)?;
+                }
+
+                for s in &self.world.other_shots {
+                    s.draw_actor(ctx, camera, self.player_color(&s.owner))?;
                 }
 
-                for r in &self.rocks {
-                    r.draw_actor(ctx, coords, self.hidpi_factor, graphics::WHITE)?;
+                for r in &self.world.rocks {
+                    r.draw_actor(ctx, camera, graphics::WHITE)?;
                 }
 
+                for p in &self.world.pickups {
+                    if let ActorType::Pickup(kind) = p.tag {
+                        p.draw_actor(ctx, camera, pickup_color(kind))?;
+                    }
+                }
+
+                let saucer_color = graphics::Color::new(0.4, 1.0, 0.4, 1.0);
+                if let Some(saucer) = &self.world.saucer {
+                    saucer.draw_actor(ctx, camera, saucer_color)?;
+                }
+                for s in &self.world.saucer_shots {
+                    s.draw_actor(ctx, camera, saucer_color)?;
+                }
+
+                self.draw_name_tags(ctx)?;
                 self.draw_ui(ctx)?;
             }
             State::Dead => {
                 self.draw_death_screen(ctx)?;
             }
+            State::GameOver => {
+                self.draw_game_over(ctx)?;
+            }
+        }
+
+        self.draw_chat(ctx)?;
+        if self.show_scoreboard {
+            self.draw_scoreboard(ctx)?;
         }
+        self.draw_letterbox(ctx)?;
 
         // Then we flip the screen.
         graphics::present(ctx)?;
@@ -529,46 +1495,141 @@ impl EventHandler for MainState {
         ctx: &mut Context,
         keycode: KeyCode,
         _keymod: KeyMods,
-        _repeat: bool,
+        repeat: bool,
     ) {
+        // While a chat message is being typed the keys are for typing, not flying. The key that
+        // opened the box may well still be held down, so its repeats mustn't send or close it.
+        if let Some(entry) = &mut self.chat_entry {
+            match keycode {
+                KeyCode::Return if !repeat => self.submit_chat(),
+                KeyCode::Escape if !repeat => self.chat_entry = None,
+                KeyCode::Back => {
+                    entry.pop();
+                }
+                KeyCode::Q => self.chat_held_q = true,
+                _ => (),
+            }
+            return;
+        }
+
         match keycode {
+            KeyCode::T | KeyCode::Return if !repeat => {
+                // Let go of everything, so the ship doesn't fly off on its own while we type.
+                self.input = InputState::default();
+                self.chat_entry = Some(String::new());
+                self.chat_skip_char = keycode == KeyCode::T;
+            }
             KeyCode::Up => {
                 self.input.yaxis = 1.0;
             }
+            KeyCode::Down => {
+                self.input.yaxis = -1.0;
+            }
+            KeyCode::A => {
+                self.input.strafe = -1.0;
+            }
+            KeyCode::D => {
+                self.input.strafe = 1.0;
+            }
+            KeyCode::Left if self.choosing_class() => self.cycle_class(-1),
+            KeyCode::Right if self.choosing_class() => self.cycle_class(1),
             KeyCode::Left => {
                 self.input.xaxis = -1.0;
             }
             KeyCode::Right => {
                 self.input.xaxis = 1.0;
             }
-            KeyCode::Space => {
+            // Fire stays on until the key comes up, so a held key repeating adds nothing.
+            KeyCode::Space if !repeat => {
                 self.input.fire = true;
             }
+            KeyCode::H => {
+                self.input.hyperspace = true;
+            }
+            KeyCode::Key1 => self.world.select_weapon(0),
+            KeyCode::Key2 => self.world.select_weapon(1),
+            KeyCode::Key3 => self.world.select_weapon(2),
+            KeyCode::Key4 => self.world.select_weapon(3),
+            KeyCode::S => {
+                self.input.shield = true;
+            }
             KeyCode::P => {
                 let img = graphics::screenshot(ctx).expect("Could not take screenshot");
                 img.encode(ctx, graphics::ImageFormat::Png, "/screenshot.png")
                     .expect("Could not save screenshot");
             }
-            KeyCode::Escape => ggez::quit(ctx),
+            KeyCode::R if self.network.status() != ConnectionStatus::Connected => {
+                self.network.restart();
+            }
+            KeyCode::F3 => {
+                self.show_net_stats = !self.show_net_stats;
+            }
+            KeyCode::M => {
+                self.show_radar = !self.show_radar;
+            }
+            KeyCode::Tab => {
+                self.show_scoreboard = true;
+            }
+            KeyCode::Escape => self.quit(ctx),
             _ => (),
         }
     }
 
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        self.fit_to_window(ctx, width, height)
+            .expect("could not fit the world to the window");
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) {
+        let skip = self.chat_skip_char && character.eq_ignore_ascii_case(&'t');
+
+        if let Some(entry) = &mut self.chat_entry {
+            if !skip && !character.is_control() && entry.chars().count() < chat::MAX_CHAT_LEN {
+                entry.push(character);
+            }
+        }
+    }
+
     fn key_up_event(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods) {
+        // Letting go of a key only ever stops something, so it counts even while a chat message
+        // is being typed. Otherwise whatever was held when the box opened would stay on.
         match keycode {
-            KeyCode::Up => {
+            KeyCode::T => {
+                self.chat_skip_char = false;
+            }
+            KeyCode::Up | KeyCode::Down => {
                 self.input.yaxis = 0.0;
             }
+            KeyCode::A | KeyCode::D => {
+                self.input.strafe = 0.0;
+            }
             KeyCode::Left | KeyCode::Right => {
                 self.input.xaxis = 0.0;
             }
             KeyCode::Space => {
                 self.input.fire = false;
             }
+            KeyCode::H => {
+                self.input.hyperspace = false;
+            }
+            KeyCode::Tab => {
+                self.show_scoreboard = false;
+            }
+            KeyCode::S => {
+                self.input.shield = false;
+            }
             KeyCode::Q => {
-                let _ = ggez::quit(ctx);
+                let typed = std::mem::replace(&mut self.chat_held_q, false);
+                if !typed && self.chat_entry.is_none() {
+                    self.quit(ctx);
+                }
             }
             _ => (),
         }
     }
+
+    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
+        self.network.shutdown();
+        false
+    }
 }
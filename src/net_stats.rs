@@ -0,0 +1,233 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+// How far back we look when working out bandwidth.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+// How many sequence numbers we remember per peer when working out packet loss.
+const LOSS_WINDOW: usize = 120;
+// Pings we haven't heard back about after this long are considered lost.
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Counts bytes over a sliding window so we can report a rate.
+#[derive(Debug, Default)]
+struct RateCounter {
+    samples: VecDeque<(Instant, usize)>,
+}
+
+impl RateCounter {
+    fn record(&mut self, now: Instant, bytes: usize) {
+        self.samples.push_back((now, bytes));
+        self.expire(now);
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some(&(at, _)) = self.samples.front() {
+            if now.duration_since(at) > RATE_WINDOW {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn bytes_per_second(&mut self, now: Instant) -> f32 {
+        self.expire(now);
+        let total: usize = self.samples.iter().map(|&(_, bytes)| bytes).sum();
+        total as f32 / RATE_WINDOW.as_secs() as f32
+    }
+}
+
+/// What we know about the link to a single peer.
+#[derive(Debug)]
+struct PeerStats {
+    last_seen: Instant,
+    rtt: Option<Duration>,
+    seqs: VecDeque<u32>,
+    bytes_in: RateCounter,
+}
+
+impl PeerStats {
+    fn new(now: Instant) -> Self {
+        PeerStats {
+            last_seen: now,
+            rtt: None,
+            seqs: VecDeque::new(),
+            bytes_in: RateCounter::default(),
+        }
+    }
+
+    /// The fraction of packets we expected from this peer but never saw, between 0 and 1.
+    fn loss(&self) -> f32 {
+        let (min, max) = match (self.seqs.iter().min(), self.seqs.iter().max()) {
+            (Some(&min), Some(&max)) => (min, max),
+            _ => return 0.0,
+        };
+        let expected = (max - min) as f32 + 1.0;
+        1.0 - (self.seqs.len() as f32 / expected).min(1.0)
+    }
+}
+
+/// A summary of one peer's link, ready for display.
+#[derive(Debug)]
+pub struct PeerSummary {
+    pub age: Duration,
+    pub rtt: Option<Duration>,
+    pub loss: f32,
+    pub bytes_in: f32,
+    pub bytes_out: f32,
+}
+
+/// Keeps track of per-peer link quality: when we last heard from each peer, round-trip times from
+/// ping/pong messages, packet loss from message sequence numbers and bandwidth in both directions.
+#[derive(Debug, Default)]
+pub struct NetStats {
+    peers: HashMap<String, PeerStats>,
+    bytes_out: RateCounter,
+    pings: HashMap<u32, Instant>,
+    next_ping: u32,
+}
+
+impl NetStats {
+    /// Records a message arriving from the given peer.
+    pub fn received(&mut self, peer: &str, seq: Option<u32>, bytes: usize, now: Instant) {
+        let stats = self
+            .peers
+            .entry(peer.to_string())
+            .or_insert_with(|| PeerStats::new(now));
+        stats.last_seen = now;
+        stats.bytes_in.record(now, bytes);
+
+        if let Some(seq) = seq {
+            // A peer that restarts its counter starts a new window. A packet from not so long ago
+            // just took the long way round, and still counts as having arrived.
+            match stats.seqs.iter().max() {
+                Some(&newest) if seq < newest && newest - seq > LOSS_WINDOW as u32 => {
+                    stats.seqs.clear()
+                }
+                _ => {}
+            }
+            if !stats.seqs.contains(&seq) {
+                stats.seqs.push_back(seq);
+                if stats.seqs.len() > LOSS_WINDOW {
+                    stats.seqs.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Records a message we multicast. Everyone receives everything, so this counts against
+    /// every peer.
+    pub fn sent(&mut self, bytes: usize, now: Instant) {
+        self.bytes_out.record(now, bytes);
+    }
+
+    /// Hands out the sequence number for a new ping and remembers when we sent it.
+    pub fn start_ping(&mut self, now: Instant) -> u32 {
+        let seq = self.next_ping;
+        self.next_ping = self.next_ping.wrapping_add(1);
+        self.pings.insert(seq, now);
+        self.pings
+            .retain(|_, &mut sent| now.duration_since(sent) < PING_TIMEOUT);
+        seq
+    }
+
+    /// Records a peer answering one of our pings.
+    pub fn pong(&mut self, peer: &str, seq: u32, now: Instant) {
+        if let (Some(&sent), Some(stats)) = (self.pings.get(&seq), self.peers.get_mut(peer)) {
+            stats.rtt = Some(now.duration_since(sent));
+        }
+    }
+
+    /// Our average round-trip time to the peers we've heard back from, if any.
+    pub fn average_rtt(&self) -> Option<Duration> {
+        let rtts: Vec<Duration> = self.peers.values().filter_map(|stats| stats.rtt).collect();
+        if rtts.is_empty() {
+            return None;
+        }
+        Some(rtts.iter().sum::<Duration>() / rtts.len() as u32)
+    }
+
+    /// The peers we haven't heard a thing from in over `timeout`.
+    pub fn silent_peers(&self, now: Instant, timeout: Duration) -> Vec<String> {
+        self.peers
+            .iter()
+            .filter(|(_, stats)| now.duration_since(stats.last_seen) > timeout)
+            .map(|(peer, _)| peer.clone())
+            .collect()
+    }
+
+    pub fn forget(&mut self, peer: &str) {
+        self.peers.remove(peer);
+    }
+
+    pub fn summary(&mut self, peer: &str, now: Instant) -> Option<PeerSummary> {
+        let bytes_out = self.bytes_out.bytes_per_second(now);
+        self.peers.get_mut(peer).map(|stats| PeerSummary {
+            age: now.duration_since(stats.last_seen),
+            rtt: stats.rtt,
+            loss: stats.loss(),
+            bytes_in: stats.bytes_in.bytes_per_second(now),
+            bytes_out,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loss() {
+        let now = Instant::now();
+        let mut stats = NetStats::default();
+
+        for seq in &[0, 1, 3, 4, 5, 8, 9] {
+            stats.received("peer", Some(*seq), 10, now);
+        }
+
+        let loss = stats.summary("peer", now).unwrap().loss;
+        assert!((loss - 0.3).abs() < 0.001);
+
+        // Packets that turn up late fill their gaps rather than starting over...
+        stats.received("peer", Some(2), 10, now);
+        stats.received("peer", Some(6), 10, now);
+        let loss = stats.summary("peer", now).unwrap().loss;
+        assert!((loss - 0.1).abs() < 0.001);
+
+        // ...but a counter that jumps right back means the peer restarted.
+        stats.received("peer", Some(1000), 10, now);
+        stats.received("peer", Some(0), 10, now);
+        assert_eq!(stats.summary("peer", now).unwrap().loss, 0.0);
+    }
+
+    #[test]
+    fn test_rtt() {
+        let now = Instant::now();
+        let mut stats = NetStats::default();
+        stats.received("peer", None, 10, now);
+
+        let seq = stats.start_ping(now);
+        stats.pong("peer", seq, now + Duration::from_millis(40));
+
+        let rtt = stats.summary("peer", now).unwrap().rtt;
+        assert_eq!(rtt, Some(Duration::from_millis(40)));
+
+        stats.received("other", None, 10, now);
+        assert_eq!(stats.average_rtt(), Some(Duration::from_millis(40)));
+        let seq = stats.start_ping(now);
+        stats.pong("other", seq, now + Duration::from_millis(20));
+        assert_eq!(stats.average_rtt(), Some(Duration::from_millis(30)));
+    }
+
+    #[test]
+    fn test_silent_peers() {
+        let now = Instant::now();
+        let mut stats = NetStats::default();
+        stats.received("quiet", None, 10, now);
+        stats.received("chatty", None, 10, now + Duration::from_secs(4));
+
+        let later = now + Duration::from_secs(6);
+        let silent = stats.silent_peers(later, Duration::from_secs(5));
+        assert_eq!(silent, vec!["quiet".to_string()]);
+    }
+}
@@ -0,0 +1,228 @@
+use crate::HashMapCodec;
+use futures::sync::mpsc::{unbounded, UnboundedSender};
+use std::{
+    collections::HashMap,
+    fmt, io,
+    net::{SocketAddr, SocketAddrV4},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+use tokio::net::{UdpFramed, UdpSocket};
+use tokio::prelude::*;
+
+/// Everything we put on the wire is a flat map of names to numbers.
+pub type Message = HashMap<String, f64>;
+
+/// The `tag` of a message tells the receiver how to interpret the rest of it.
+pub const TAG_PLAYER: f64 = 1.0;
+pub const TAG_GOODBYE: f64 = 2.0;
+pub const TAG_PING: f64 = 3.0;
+pub const TAG_PONG: f64 = 4.0;
+pub const TAG_ROCK_HIT: f64 = 5.0;
+pub const TAG_ROCK_SPAWN: f64 = 6.0;
+pub const TAG_SAUCER: f64 = 7.0;
+pub const TAG_SAUCER_SHOT: f64 = 8.0;
+pub const TAG_SAUCER_HIT: f64 = 9.0;
+pub const TAG_PICKUP_CLAIM: f64 = 10.0;
+pub const TAG_SHOT: f64 = 11.0;
+pub const TAG_CHAT: f64 = 12.0;
+pub const TAG_CHAT_ACK: f64 = 13.0;
+
+// How many receive errors in a row we put up with before giving up on the socket.
+const MAX_CONSECUTIVE_ERRORS: u32 = 10;
+
+/// How the network thread is getting on, as far as the game is concerned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionStatus {
+    Connected,
+    Degraded,
+    Failed,
+}
+
+impl fmt::Display for ConnectionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            ConnectionStatus::Connected => "connected",
+            ConnectionStatus::Degraded => "degraded",
+            ConnectionStatus::Failed => "failed",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+fn bind_multicast(
+    addr: &SocketAddrV4,
+    multi: &SocketAddrV4,
+) -> Result<std::net::UdpSocket, std::io::Error> {
+    use socket2::{Domain, Protocol, Socket, Type};
+
+    let socket = Socket::new(Domain::ipv4(), Type::dgram(), Some(Protocol::udp()))?;
+
+    socket.set_reuse_address(true)?;
+    socket.bind(&socket2::SockAddr::from(*addr))?;
+    socket.set_multicast_loop_v4(true)?;
+    socket.join_multicast_v4(multi.ip(), addr.ip())?;
+
+    Ok(socket.into_udp_socket())
+}
+
+/// A handle to the thread running the tokio runtime that shuffles messages between the game and
+/// the multicast socket. The game owns this, so it can ask how the connection is doing, restart
+/// the socket when things go wrong and shut everything down when it quits.
+pub struct Network {
+    addr: SocketAddrV4,
+    multicast: SocketAddrV4,
+    tx: Option<UnboundedSender<Message>>,
+    rx: mpsc::Receiver<Message>,
+    status: Arc<Mutex<ConnectionStatus>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Network {
+    /// Binds the socket and spawns the network thread. This never fails outright; if the socket
+    /// can't be bound the handle simply reports `ConnectionStatus::Failed`.
+    pub fn start(addr: SocketAddrV4, multicast: SocketAddrV4) -> Network {
+        assert!(multicast.ip().is_multicast(), "Must be multcast address");
+
+        let (_, rx) = mpsc::channel();
+        let mut network = Network {
+            addr,
+            multicast,
+            tx: None,
+            rx,
+            status: Arc::new(Mutex::new(ConnectionStatus::Failed)),
+            thread: None,
+        };
+        network.spawn();
+        network
+    }
+
+    fn spawn(&mut self) {
+        println!("Starting server on: {}", self.addr);
+        println!("Multicast address: {}\n", self.multicast);
+
+        let status = Arc::new(Mutex::new(ConnectionStatus::Connected));
+        self.status = status.clone();
+
+        let socket = bind_multicast(&self.addr, &self.multicast).and_then(|std_socket| {
+            UdpSocket::from_std(std_socket, &tokio::reactor::Handle::default())
+        });
+        let socket = match socket {
+            Ok(socket) => socket,
+            Err(e) => {
+                println!("Failed to bind multicast socket: {:?}", e);
+                *status.lock().unwrap() = ConnectionStatus::Failed;
+                return;
+            }
+        };
+
+        let framed = UdpFramed::new(socket, HashMapCodec {});
+        let (udp_tx, udp_rx) = Stream::split(framed);
+        let (chn_tx, chn_rx) = unbounded::<Message>();
+
+        let maddr = self.multicast;
+        let send = chn_rx
+            .map(move |s| (s, SocketAddr::from(maddr)))
+            .forward(udp_tx.sink_map_err(|e| println!("Error sending UDP packet: {:?}", e)))
+            .map(|_| ());
+
+        let (tx, rx) = mpsc::channel();
+
+        let recv_status = status.clone();
+        let mut errors = 0;
+        let recv = udp_rx.then(Ok::<_, ()>).for_each(move |result| {
+            let mut status = recv_status.lock().unwrap();
+            match result {
+                Ok((map, ip)) => {
+                    errors = 0;
+                    *status = ConnectionStatus::Connected;
+
+                    let mut map = map.clone();
+                    map.insert(format!("ip-{}", ip), 0.0);
+                    // The game has gone away, so there's no one left to listen.
+                    tx.send(map).map_err(|_| ())
+                }
+                // Somebody sent us garbage, which says nothing about the socket itself.
+                Err(ref e) if e.kind() == io::ErrorKind::InvalidData => {
+                    println!("Skipping malformed UDP packet: {:?}", e);
+                    Ok(())
+                }
+                Err(e) => {
+                    println!("Error receiving UDP packet: {:?}", e);
+                    errors += 1;
+                    if errors >= MAX_CONSECUTIVE_ERRORS {
+                        Err(())
+                    } else {
+                        *status = ConnectionStatus::Degraded;
+                        Ok(())
+                    }
+                }
+            }
+        });
+
+        let serve_status = status.clone();
+        let serve = send.select(recv).then(move |result| {
+            if result.is_err() {
+                *serve_status.lock().unwrap() = ConnectionStatus::Failed;
+            }
+            Ok(())
+        });
+
+        self.tx = Some(chn_tx);
+        self.rx = rx;
+        self.thread = Some(thread::spawn(move || {
+            tokio::run(serve);
+        }));
+    }
+
+    /// Stops the network thread. Dropping our end of the outgoing channel lets the send half
+    /// flush whatever is still queued and finish, which in turn brings down the runtime.
+    fn stop(&mut self) {
+        self.tx = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+
+    pub fn status(&self) -> ConnectionStatus {
+        *self.status.lock().unwrap()
+    }
+
+    /// Queues a message to be multicast to everyone, ourselves included.
+    pub fn send(&mut self, map: Message) {
+        let sent = match &self.tx {
+            Some(tx) => tx.unbounded_send(map).is_ok(),
+            None => false,
+        };
+        if !sent {
+            *self.status.lock().unwrap() = ConnectionStatus::Failed;
+        }
+    }
+
+    /// Takes the next message that has arrived, if there is one.
+    pub fn try_recv(&self) -> Option<Message> {
+        self.rx.try_recv().ok()
+    }
+
+    /// Tears down the socket and the network thread and starts them up again from scratch.
+    pub fn restart(&mut self) {
+        self.stop();
+        self.spawn();
+    }
+
+    /// Lets everyone know we're leaving, then stops the network thread.
+    pub fn shutdown(&mut self) {
+        if self.tx.is_some() {
+            let mut goodbye = Message::new();
+            goodbye.insert("tag".to_string(), TAG_GOODBYE);
+            self.send(goodbye);
+        }
+        self.stop();
+    }
+}
+
+impl Drop for Network {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,221 @@
+use crate::actor::ShipClass;
+use crate::arena::Arena;
+use crate::flight::FlightModel;
+use crate::{VIEW_HEIGHT, VIEW_WIDTH};
+use std::collections::HashMap;
+use std::fs;
+
+/// The longest name a player can go by, so name tags stay readable.
+pub const MAX_NAME_LEN: usize = 16;
+
+/// A corner of the screen, for putting things in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    pub fn parse(text: &str) -> Option<Corner> {
+        match text {
+            "top-left" => Some(Corner::TopLeft),
+            "top-right" => Some(Corner::TopRight),
+            "bottom-left" => Some(Corner::BottomLeft),
+            "bottom-right" => Some(Corner::BottomRight),
+            _ => None,
+        }
+    }
+}
+
+/// The knobs that can be turned from the command line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// Seeds every random decision the world makes, so the same seed gives the same game. In a
+    /// multiplayer game everyone plays among the rocks of whoever's in charge, from their seed.
+    pub seed: u64,
+    /// How many times a second the simulation is stepped.
+    pub tick_rate: u32,
+    /// Whether rocks bounce off rocks and ships off ships. In a multiplayer game whoever's in
+    /// charge decides for everyone.
+    pub bounce: bool,
+    /// How each class of ship handles. The file given with `--flight` tunes them all at once, or
+    /// one class at a time under a `[scout]` style header.
+    pub flight: HashMap<ShipClass, FlightModel>,
+    /// The stars, obstacles and wormholes to play among, read from the file given with `--arena`.
+    pub arena: Arena,
+    /// The width and height of the world. It's never smaller than the screen, and when it's bigger
+    /// the screen scrolls to follow the ship. In a multiplayer game everyone plays in the world of
+    /// whoever's in charge.
+    pub world_size: (f32, f32),
+    /// Which corner of the screen the radar goes in.
+    pub radar_corner: Corner,
+    /// What other players see under our ship. Without one we're given a number.
+    pub name: Option<String>,
+    /// Our colour as `0xRRGGBB`. Without one we're given one of a handful of distinct colours.
+    pub color: Option<u32>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            seed: rand::random(),
+            tick_rate: 60,
+            bounce: false,
+            flight: ShipClass::ALL.iter().map(|&c| (c, c.flight())).collect(),
+            arena: Arena::default(),
+            world_size: (VIEW_WIDTH, VIEW_HEIGHT),
+            radar_corner: Corner::TopRight,
+            name: None,
+            color: None,
+        }
+    }
+}
+
+impl Settings {
+    /// Parses settings from command line arguments, not including the program name.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Settings, String> {
+        let mut settings = Settings::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => {
+                    let value = args.next().ok_or("--seed needs a value")?;
+                    settings.seed = value
+                        .parse()
+                        .map_err(|_| format!("invalid seed: {}", value))?;
+                }
+                "--tick-rate" => {
+                    let value = args.next().ok_or("--tick-rate needs a value")?;
+                    settings.tick_rate = match value.parse() {
+                        Ok(tick_rate) if tick_rate > 0 => tick_rate,
+                        _ => return Err(format!("invalid tick rate: {}", value)),
+                    };
+                }
+                "--bounce" => settings.bounce = true,
+                "--flight" => {
+                    let path = args.next().ok_or("--flight needs a file")?;
+                    let text = fs::read_to_string(&path)
+                        .map_err(|e| format!("could not read {}: {}", path, e))?;
+                    for &class in ShipClass::ALL.iter() {
+                        let model = FlightModel::parse_section(class.flight(), &text, class.name())
+                            .map_err(|e| format!("{}: {}", path, e))?;
+                        settings.flight.insert(class, model);
+                    }
+                }
+                "--arena" => {
+                    let path = args.next().ok_or("--arena needs a file")?;
+                    let text = fs::read_to_string(&path)
+                        .map_err(|e| format!("could not read {}: {}", path, e))?;
+                    settings.arena = Arena::parse(&text).map_err(|e| format!("{}: {}", path, e))?;
+                }
+                "--world-size" => {
+                    let value = args.next().ok_or("--world-size needs a value")?;
+                    let invalid = || format!("invalid world size: {}", value);
+                    let mut parts = value.splitn(2, 'x');
+                    let mut dimension = || -> Result<f32, String> {
+                        parts
+                            .next()
+                            .and_then(|p| p.parse().ok())
+                            .filter(|d: &f32| d.is_finite())
+                            .ok_or_else(invalid)
+                    };
+                    let size = (dimension()?, dimension()?);
+                    if size.0 < VIEW_WIDTH || size.1 < VIEW_HEIGHT {
+                        return Err(format!(
+                            "world size {} is smaller than the screen ({}x{})",
+                            value, VIEW_WIDTH, VIEW_HEIGHT
+                        ));
+                    }
+                    settings.world_size = size;
+                }
+                "--radar" => {
+                    let value = args.next().ok_or("--radar needs a corner")?;
+                    settings.radar_corner = Corner::parse(&value)
+                        .ok_or_else(|| format!("invalid corner: {}", value))?;
+                }
+                "--name" => {
+                    let value = args.next().ok_or("--name needs a value")?;
+                    let len = value.chars().count();
+                    if len == 0 || len > MAX_NAME_LEN {
+                        return Err(format!("names must be 1 to {} letters", MAX_NAME_LEN));
+                    }
+                    settings.name = Some(value);
+                }
+                "--color" => {
+                    let value = args.next().ok_or("--color needs a value")?;
+                    let color = match u32::from_str_radix(&value, 16) {
+                        Ok(color) if value.len() == 6 && !value.starts_with('+') => color,
+                        _ => return Err(format!("invalid color, expected RRGGBB: {}", value)),
+                    };
+                    settings.color = Some(color);
+                }
+                _ => return Err(format!("unknown argument: {}", arg)),
+            }
+        }
+
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seed() {
+        let args = vec!["--seed".to_string(), "42".to_string()];
+        let settings = Settings::from_args(args).unwrap();
+        assert_eq!(settings.seed, 42);
+
+        assert!(Settings::from_args(vec!["--seed".to_string()]).is_err());
+        assert!(Settings::from_args(vec!["--bogus".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_tick_rate() {
+        let args = vec!["--tick-rate".to_string(), "120".to_string()];
+        let settings = Settings::from_args(args).unwrap();
+        assert_eq!(settings.tick_rate, 120);
+
+        let args = vec!["--tick-rate".to_string(), "0".to_string()];
+        assert!(Settings::from_args(args).is_err());
+    }
+
+    #[test]
+    fn test_world_size() {
+        let args = vec!["--world-size".to_string(), "3200x2400".to_string()];
+        let settings = Settings::from_args(args).unwrap();
+        assert_eq!(settings.world_size, (3200.0, 2400.0));
+
+        for bad in &["3200", "3200xtall", "400x300", "NaNxNaN", "infxinf"] {
+            let args = vec!["--world-size".to_string(), bad.to_string()];
+            assert!(Settings::from_args(args).is_err());
+        }
+    }
+
+    #[test]
+    fn test_radar_corner() {
+        let args = vec!["--radar".to_string(), "bottom-left".to_string()];
+        let settings = Settings::from_args(args).unwrap();
+        assert_eq!(settings.radar_corner, Corner::BottomLeft);
+
+        let args = vec!["--radar".to_string(), "middle".to_string()];
+        assert!(Settings::from_args(args).is_err());
+    }
+
+    #[test]
+    fn test_name_and_color() {
+        let args = vec!["--name", "Ripley", "--color", "ff8800"];
+        let settings = Settings::from_args(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(settings.name, Some("Ripley".to_string()));
+        assert_eq!(settings.color, Some(0xff8800));
+
+        let args = vec!["--name".to_string(), "x".repeat(MAX_NAME_LEN + 1)];
+        assert!(Settings::from_args(args).is_err());
+        let args = vec!["--color".to_string(), "orange".to_string()];
+        assert!(Settings::from_args(args).is_err());
+    }
+}
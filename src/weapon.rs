@@ -0,0 +1,119 @@
+use crate::actor::Actor;
+use crate::{Point2, Vector2};
+
+/// Everything about how a ship's gun fires. Weapons are plain data, so the power-ups can make a
+/// souped-up copy of one and peers can rebuild a volley from a few numbers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weapon {
+    pub name: &'static str,
+    /// Seconds between volleys.
+    pub fire_time: f32,
+    /// Shots per volley.
+    pub projectiles: u32,
+    /// The angle in radians that a volley is fanned out over.
+    pub spread: f32,
+    /// Pixels per second, on top of the ship's own velocity.
+    pub speed: f32,
+    /// Seconds before a shot fizzles out.
+    pub lifetime: f32,
+    /// How much health a shot knocks off whatever it hits.
+    pub damage: f32,
+    /// How many rocks each shot goes through before it's used up.
+    pub pierce: u32,
+}
+
+/// The most shots and widest spread any volley can have, which is the Scatter with a spread
+/// pickup running. Volleys from peers that claim more than this are thrown away.
+pub const MAX_PROJECTILES: u32 = 7;
+pub const MAX_SPREAD: f32 = 1.1;
+
+/// The weapons every ship can pick from, in the order of the number keys.
+pub const WEAPONS: [Weapon; 4] = [
+    Weapon {
+        name: "Blaster",
+        fire_time: 0.5,
+        projectiles: 1,
+        spread: 0.0,
+        speed: 200.0,
+        lifetime: 2.0,
+        damage: 1.0,
+        pierce: 0,
+    },
+    Weapon {
+        name: "Repeater",
+        fire_time: 0.15,
+        projectiles: 1,
+        spread: 0.0,
+        speed: 250.0,
+        lifetime: 1.2,
+        damage: 0.5,
+        pierce: 0,
+    },
+    Weapon {
+        name: "Scatter",
+        fire_time: 0.8,
+        projectiles: 5,
+        spread: 0.6,
+        speed: 180.0,
+        lifetime: 0.8,
+        damage: 1.0,
+        pierce: 0,
+    },
+    Weapon {
+        name: "Lance",
+        fire_time: 1.2,
+        projectiles: 1,
+        spread: 0.0,
+        speed: 400.0,
+        lifetime: 1.5,
+        damage: 3.0,
+        pierce: 1,
+    },
+];
+
+impl Weapon {
+    /// The shots for one pull of the trigger from a ship at `pos`, facing `facing` and moving at
+    /// `velocity`. The shots are fanned out evenly, so the same numbers always give the same
+    /// volley.
+    pub fn volley(&self, owner: &str, pos: Point2, facing: f32, velocity: Vector2) -> Vec<Actor> {
+        let step = if self.projectiles > 1 {
+            self.spread / (self.projectiles - 1) as f32
+        } else {
+            0.0
+        };
+
+        (0..self.projectiles)
+            .map(|i| {
+                let angle = facing - self.spread / 2.0 + step * i as f32;
+                let mut shot = Actor::create_shot(owner.to_string());
+                shot.pos = pos;
+                shot.facing = angle;
+                shot.velocity = velocity + Vector2::new(angle.sin(), angle.cos()) * self.speed;
+                shot.lifetime = self.lifetime;
+                shot.damage = self.damage;
+                shot.pierce = self.pierce;
+                shot
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volley() {
+        let scatter = WEAPONS.iter().find(|w| w.name == "Scatter").unwrap();
+        let shots = scatter.volley("self", Point2::origin(), 1.0, Vector2::new(0.0, 0.0));
+
+        assert_eq!(shots.len(), 5);
+        assert!((shots[0].facing - 0.7).abs() < 0.001);
+        assert!((shots[2].facing - 1.0).abs() < 0.001);
+        assert!((shots[4].facing - 1.3).abs() < 0.001);
+
+        let blaster = WEAPONS[0].volley("self", Point2::origin(), 1.0, Vector2::new(0.0, 0.0));
+        assert_eq!(blaster.len(), 1);
+        assert!((blaster[0].facing - 1.0).abs() < 0.001);
+    }
+}
@@ -0,0 +1,1666 @@
+use crate::actor::{Actor, ActorType, PickupKind, RockSize, SaucerSize, ShipClass};
+use crate::arena::Arena;
+use crate::collision::{self, SpatialGrid};
+use crate::flight::FlightModel;
+use crate::weapon::{Weapon, WEAPONS};
+use crate::{Point2, Vector2};
+use ggez::nalgebra as na;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64Mcg;
+use std::collections::HashMap;
+
+/// Create a unit vector representing the given angle (in radians).
+fn vec_from_angle(angle: f32) -> Vector2 {
+    let vx = angle.sin();
+    let vy = angle.cos();
+    Vector2::new(vx, vy)
+}
+
+/// Makes a random `Vector2` with the given max magnitude.
+fn random_vec(rng: &mut Pcg64Mcg, max_magnitude: f32) -> Vector2 {
+    let angle = rng.gen::<f32>() * 2.0 * std::f32::consts::PI;
+    let mag = rng.gen::<f32>() * max_magnitude;
+    vec_from_angle(angle) * (mag)
+}
+
+const MAX_ROCK_VEL: f32 = 50.0;
+
+// How many large rocks there are on level zero; each level adds one more.
+const STARTING_ROCKS: i32 = 4;
+
+// How many ships the player gets before the game is over.
+const STARTING_LIVES: i32 = 3;
+// Seconds a freshly respawned ship can't be hurt by rocks.
+const RESPAWN_INVULNERABILITY: f32 = 3.0;
+// How many random spots we try when looking for somewhere safe to respawn.
+const RESPAWN_CANDIDATES: usize = 16;
+// How close to a star or obstacle a ship can start out.
+const ARENA_CLEARANCE: f32 = 50.0;
+
+// Seconds between hyperspace jumps, and the chance that a jump goes wrong and destroys the ship.
+const HYPERSPACE_COOLDOWN: f32 = 5.0;
+const HYPERSPACE_FAILURE_CHANCE: f64 = 0.1;
+// Seconds the shield lasts, and seconds from raising it until it can be raised again.
+const SHIELD_TIME: f32 = 2.0;
+const SHIELD_COOLDOWN: f32 = 8.0;
+
+// Seconds between one saucer leaving and the next turning up, picked at random from this range.
+const SAUCER_MIN_TIME: f32 = 10.0;
+const SAUCER_MAX_TIME: f32 = 20.0;
+const SAUCER_SPEED: f32 = 80.0;
+const SAUCER_SHOT_TIME: f32 = 1.0;
+const SAUCER_SHOT_SPEED: f32 = 150.0;
+
+/// How much health a large rock has on the given level. They get tougher every third level. The
+/// pieces they break up into always have the same health as a first-level rock.
+fn rock_health(level: i32) -> f32 {
+    1.0 + (level / 3) as f32
+}
+
+/// The dice for laying out a level's rocks. They're rolled afresh from the seed and the level, so
+/// peers sharing a seed agree on every level's rocks however differently their games have gone.
+fn level_rng(seed: u64, level: i32) -> Pcg64Mcg {
+    Pcg64Mcg::seed_from_u64(seed ^ (u64::from(level as u32) << 32))
+}
+
+/// Create the given number of large rocks. Makes sure that none of them are within the given
+/// exclusion zone. Note that this *could* create rocks outside the bounds of the playing field,
+/// so it should be called before `wrap_actor_position()` happens.
+fn create_rocks(
+    rng: &mut Pcg64Mcg,
+    num: i32,
+    health: f32,
+    exclusion: Point2,
+    min_radius: f32,
+    max_radius: f32,
+) -> Vec<Actor> {
+    assert!(max_radius > min_radius);
+    let new_rock = |_| {
+        let mut rock = Actor::create_rock("self".to_string(), RockSize::Large, rng.gen());
+        rock.health = health;
+        let r_angle = rng.gen::<f32>() * 2.0 * std::f32::consts::PI;
+        let r_distance = rng.gen::<f32>() * (max_radius - min_radius) + min_radius;
+        rock.pos = exclusion + vec_from_angle(r_angle) * r_distance;
+        rock.velocity = random_vec(rng, MAX_ROCK_VEL);
+        rock
+    };
+    (0..num).map(new_rock).collect()
+}
+
+/// The large rocks that start off the given level. They depend on nothing but the seed and the
+/// level, and keep clear of the middle of the world, where ships start out.
+fn level_rocks(seed: u64, level: i32) -> Vec<Actor> {
+    let num = STARTING_ROCKS + level;
+    let health = rock_health(level);
+    create_rocks(
+        &mut level_rng(seed, level),
+        num,
+        health,
+        Point2::origin(),
+        100.0,
+        250.0,
+    )
+}
+
+/// Breaks a rock up into two or three smaller, faster pieces flying off in random directions. The
+/// randomness is seeded from the rock's id rather than drawn from the world, so every peer that
+/// splits the same rock gets exactly the same pieces, with the same ids.
+fn split_rock(rock: &Actor) -> Vec<Actor> {
+    let smaller = match rock.tag {
+        ActorType::Rock(size) => size.smaller(),
+        _ => None,
+    };
+    let smaller = match smaller {
+        Some(smaller) => smaller,
+        None => return Vec::new(),
+    };
+
+    let mut rng = Pcg64Mcg::seed_from_u64(rock.id.into());
+    let pieces = rng.gen_range(2, 4);
+    (0..pieces)
+        .map(|_| {
+            let mut piece = Actor::create_rock(rock.owner.clone(), smaller, rng.gen());
+            piece.pos = rock.pos;
+            piece.velocity = rock.velocity + random_vec(&mut rng, MAX_ROCK_VEL * smaller.speed());
+            piece
+        })
+        .collect()
+}
+
+// The chance that a destroyed rock leaves a pickup behind.
+const PICKUP_CHANCE: f64 = 0.15;
+// How long the power-ups that don't take effect straight away last, in seconds.
+const PICKUP_EFFECT_TIME: f32 = 10.0;
+// Seconds we remember the pickups we've taken, in case somebody else turns out to have got there
+// at the same moment. It only has to be longer than a claim takes to cross the network.
+const PICKUP_CLAIM_TIME: f32 = 2.0;
+// How many rocks a piercing shot goes through before it's used up.
+const PICKUP_PIERCE: u32 = 2;
+// How much wider a spread shot fans a volley out, for each extra shot either side.
+const PICKUP_SPREAD_ANGLE: f32 = 0.25;
+
+/// Works out whether a destroyed rock leaves a pickup behind, and what it is. Like splitting,
+/// this is seeded from the rock's id, so every peer that breaks the rock gets the same pickup. The
+/// id is shifted so the dice come out differently from the ones used to split it.
+fn rock_pickup(rock: &Actor) -> Option<Actor> {
+    let mut rng = Pcg64Mcg::seed_from_u64(u64::from(rock.id) << 32);
+    if !rng.gen_bool(PICKUP_CHANCE) {
+        return None;
+    }
+
+    let kind = PickupKind::ALL[rng.gen_range(0, PickupKind::ALL.len())];
+    let mut pickup = Actor::create_pickup(rock.owner.clone(), kind, rock.id);
+    pickup.pos = rock.pos;
+    Some(pickup)
+}
+
+// Now we make functions to handle physics. We do simple Newtonian physics (so we do have
+// inertia), and cap the max speed so that we don't have to worry too much about small objects
+// clipping through each other.
+//
+// Our unit of world space is simply pixels, though we do transform the coordinate system so that
+// +y is up and -y is down.
+
+/// Turns and thrusts the ship the way the player is asking it to. Turning isn't instant: the
+/// ship's spin winds up towards the turn rate at the flight model's turn acceleration.
+fn player_handle_input(actor: &mut Actor, input: &InputState, flight: &FlightModel, dt: f32) {
+    let target_ang_vel = input.xaxis * flight.turn_rate;
+    let max_change = flight.turn_accel * dt;
+    actor.ang_vel += (target_ang_vel - actor.ang_vel)
+        .max(-max_change)
+        .min(max_change);
+
+    if input.yaxis > 0.0 {
+        player_thrust(actor, 0.0, flight.thrust * input.yaxis, dt);
+    } else if input.yaxis < 0.0 {
+        player_thrust(actor, 0.0, flight.reverse_thrust * input.yaxis, dt);
+    }
+    if input.strafe != 0.0 {
+        player_thrust(
+            actor,
+            std::f32::consts::FRAC_PI_2,
+            flight.strafe_thrust * input.strafe,
+            dt,
+        );
+    }
+}
+
+/// Accelerates the actor at `thrust` in the direction `angle` away from where it's facing.
+fn player_thrust(actor: &mut Actor, angle: f32, thrust: f32, dt: f32) {
+    let direction_vector = vec_from_angle(actor.facing + angle);
+    let thrust_vector = direction_vector * (thrust);
+    actor.velocity += thrust_vector * (dt);
+}
+
+/// Slows the actor down by the given fraction of its velocity per second.
+fn apply_drag(actor: &mut Actor, drag: f32, dt: f32) {
+    actor.velocity *= (1.0 - drag * dt).max(0.0);
+}
+
+/// Moves the actor along, pulled by the arena's stars and carried off by any wormhole it flies
+/// into.
+fn update_actor_position(actor: &mut Actor, arena: &Arena, width: f32, height: f32, dt: f32) {
+    actor.velocity += arena.gravity(actor.pos, width, height) * (dt);
+    let dv = actor.velocity * (dt);
+    actor.pos += dv;
+    actor.facing += actor.ang_vel * (dt);
+    if let Some(exit) = arena.wormhole_exit(actor.pos, actor.velocity, width, height) {
+        actor.pos = exit;
+    }
+}
+
+/// Pushes the actor out of any obstacle it has run into, and turns it away so it's heading
+/// back out.
+fn bounce_off_obstacles(actor: &mut Actor, arena: &Arena, width: f32, height: f32) {
+    if let Some((normal, depth)) = arena.obstacle_push(actor.pos, actor.bbox_size, width, height) {
+        actor.pos += normal * depth;
+        let towards = actor.velocity.dot(&normal);
+        if towards < 0.0 {
+            actor.velocity -= normal * (2.0 * towards);
+        }
+    }
+}
+
+// The size of a cell in the collision broad phase. A bit bigger than anything in the world, so an
+// actor never spans more than a couple of cells in each direction.
+const GRID_CELL_SIZE: f32 = 50.0;
+
+fn clamp_actor_velocity(actor: &mut Actor, max_speed: f32) {
+    // Make sure players can't go too fast to get hectic.
+    let norm_sq = actor.velocity.norm_squared();
+    if norm_sq > max_speed.powi(2) {
+        actor.velocity = actor.velocity / norm_sq.sqrt() * max_speed;
+    }
+}
+
+/// Takes an actor and wraps its position to the bounds of the screen, so if it goes off the left
+/// side of the screen it will re-enter on the right side and so on.
+fn wrap_actor_position(actor: &mut Actor, sx: f32, sy: f32) {
+    // Wrap screen.
+    let screen_x_bounds = sx / 2.0;
+    let screen_y_bounds = sy / 2.0;
+    if actor.pos.x > screen_x_bounds {
+        actor.pos.x -= sx;
+    } else if actor.pos.x < -screen_x_bounds {
+        actor.pos.x += sx;
+    };
+    if actor.pos.y > screen_y_bounds {
+        actor.pos.y -= sy;
+    } else if actor.pos.y < -screen_y_bounds {
+        actor.pos.y += sy;
+    }
+}
+
+/// Counts down how long the actor has left to live, and how much longer it glows from being hit.
+fn handle_timed_life(actor: &mut Actor, dt: f32) {
+    actor.lifetime -= dt;
+    actor.flash = (actor.flash - dt).max(0.0);
+}
+
+/// Counts down the timers on a ship's invulnerability, shield and ability cooldowns.
+fn handle_ship_timers(actor: &mut Actor, dt: f32) {
+    handle_timed_life(actor, dt);
+    let tick = |t: f32| (t - dt).max(0.0);
+    actor.invulnerable = tick(actor.invulnerable);
+    actor.hit_grace = tick(actor.hit_grace);
+    actor.shield = tick(actor.shield);
+    actor.shield_cooldown = tick(actor.shield_cooldown);
+    actor.hyperspace_cooldown = tick(actor.hyperspace_cooldown);
+}
+
+// Seconds a ship can't be hurt again after taking a hit.
+const HIT_GRACE: f32 = 1.0;
+
+/// Damages a ship, which then gets a moment's grace so that whatever hit it doesn't go on hitting
+/// it every step. Unlike a fresh ship's invulnerability, the grace doesn't make it blink.
+fn hurt_ship(ship: &mut Actor, damage: f32) {
+    ship.take_damage(damage);
+    ship.hit_grace = HIT_GRACE;
+}
+
+/// The `InputState` is exactly what it sounds like, it just keeps track of the user's input state
+/// so that we turn keyboard events into something state-based and device-independent.
+#[derive(Debug)]
+pub struct InputState {
+    pub xaxis: f32,
+    pub yaxis: f32,
+    /// Sideways thrust, positive to the right.
+    pub strafe: f32,
+    pub fire: bool,
+    pub hyperspace: bool,
+    pub shield: bool,
+}
+
+impl Default for InputState {
+    fn default() -> Self {
+        InputState {
+            xaxis: 0.0,
+            yaxis: 0.0,
+            strafe: 0.0,
+            fire: false,
+            hyperspace: false,
+            shield: false,
+        }
+    }
+}
+
+/// Something that happened during a step of the simulation that the outside world might want to
+/// react to, by playing a sound for example.
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    ShotFired {
+        pos: Point2,
+        facing: f32,
+        velocity: Vector2,
+        weapon: Weapon,
+    },
+    RockSpawned {
+        id: u32,
+    },
+    RockHit {
+        id: u32,
+        damage: f32,
+    },
+    RockDestroyed {
+        id: u32,
+        pos: Point2,
+    },
+    SaucerFired {
+        pos: Point2,
+        velocity: Vector2,
+    },
+    SaucerHit {
+        id: u32,
+        damage: f32,
+    },
+    SaucerDestroyed {
+        id: u32,
+        pos: Point2,
+    },
+    PickupClaimed {
+        id: u32,
+        kind: PickupKind,
+    },
+    PlayerDied {
+        pos: Point2,
+    },
+}
+
+/// The `World` is the simulation itself: all the actors and the rules for how they move and
+/// interact. It knows nothing about windows, sounds or sockets, so it can be stepped on its own in
+/// tests or on a headless server. Anything interesting that happens is reported back as an `Event`.
+#[derive(Debug)]
+pub struct World {
+    pub player: Actor,
+    pub other_players: HashMap<String, Actor>,
+    pub shots: Vec<Actor>,
+    /// Shots fired by other players. They're just for show: other players tell us what they hit.
+    pub other_shots: Vec<Actor>,
+    pub rocks: Vec<Actor>,
+    /// There's only ever one saucer about at a time.
+    pub saucer: Option<Actor>,
+    pub saucer_shots: Vec<Actor>,
+    /// Pickups share the id of the rock that dropped them.
+    pub pickups: Vec<Actor>,
+    /// The power-ups the player has running, and the seconds each has left.
+    pub effects: HashMap<PickupKind, f32>,
+    // The pickups we've taken lately, by id, with what they were and how long ago we took them.
+    claims: HashMap<u32, (PickupKind, f32)>,
+    pub level: i32,
+    pub score: i32,
+    /// Rocks and saucers the player has finished off this game.
+    pub kills: u32,
+    /// Ships the player has lost this game.
+    pub deaths: u32,
+    /// Ships left, counting the one we're flying.
+    pub lives: i32,
+    pub width: f32,
+    pub height: f32,
+    /// Whether rocks bounce off rocks and ships off ships, rather than passing through.
+    pub bounce: bool,
+    /// How the local player's ship handles.
+    pub flight: FlightModel,
+    arena: Arena,
+    /// Whether this world sends out saucers and decides what they do. In a multiplayer game only
+    /// one peer does; everyone else just moves the saucers they're told about.
+    pub simulate_saucers: bool,
+    /// Whether this world moves on to the next level when the rocks run out. Like saucers, only
+    /// one peer does in a multiplayer game, and everyone else follows its lead.
+    pub advance_levels: bool,
+    player_shot_timeout: f32,
+    saucer_timeout: f32,
+    saucer_shot_timeout: f32,
+    // The last saucer we saw destroyed, so late news of it from its owner doesn't bring it back.
+    last_destroyed_saucer: Option<u32>,
+    seed: u64,
+    // A generator with a name rather than `StdRng`, which can change from one version of rand or
+    // platform to the next. Every peer has to roll exactly the same dice from the same seed. This
+    // one is for respawns, hyperspace and saucers; each level's rocks get dice of their own.
+    rng: Pcg64Mcg,
+}
+
+impl World {
+    /// Creates a new world. Everything random about it is drawn from `seed`, so two worlds made
+    /// with the same seed and fed the same input play out identically.
+    pub fn new(width: f32, height: f32, seed: u64) -> World {
+        let mut rng = Pcg64Mcg::seed_from_u64(seed);
+        let player = Actor::create_player("self".to_string(), ShipClass::default());
+        let rocks = level_rocks(seed, 0);
+
+        World {
+            player,
+            other_players: HashMap::new(),
+            shots: Vec::new(),
+            other_shots: Vec::new(),
+            rocks,
+            saucer: None,
+            saucer_shots: Vec::new(),
+            pickups: Vec::new(),
+            effects: HashMap::new(),
+            claims: HashMap::new(),
+            level: 0,
+            score: 0,
+            kills: 0,
+            deaths: 0,
+            lives: STARTING_LIVES,
+            width,
+            height,
+            bounce: false,
+            flight: ShipClass::default().flight(),
+            arena: Arena::default(),
+            simulate_saucers: true,
+            advance_levels: true,
+            player_shot_timeout: 0.0,
+            saucer_timeout: rng.gen_range(SAUCER_MIN_TIME, SAUCER_MAX_TIME),
+            saucer_shot_timeout: 0.0,
+            last_destroyed_saucer: None,
+            seed,
+            rng,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Swaps the rocks for those at the start of `level` in a game seeded with `seed`.
+    pub fn set_field(&mut self, seed: u64, level: i32) {
+        self.seed = seed;
+        self.level = level;
+        self.rocks = level_rocks(seed, level);
+        self.pickups.clear();
+    }
+
+    /// Falls in with whoever's running the field, if they're playing from a different seed or on
+    /// a different level. Our rocks are no good then, so they go, and theirs arrive through
+    /// `add_rock`. We don't make them afresh from the seed: by now they'll have shot some of the
+    /// level's rocks to pieces and moved the rest on.
+    pub fn follow_field(&mut self, seed: u64, level: i32) {
+        if (seed, level) == (self.seed, self.level) {
+            return;
+        }
+        self.seed = seed;
+        self.level = level;
+        self.rocks.clear();
+        self.pickups.clear();
+    }
+
+    /// Resizes the world, so we can play in the same one as whoever's in charge. Everything is
+    /// brought back inside the new edges.
+    pub fn set_size(&mut self, width: f32, height: f32) {
+        if (width, height) == (self.width, self.height) {
+            return;
+        }
+        self.width = width;
+        self.height = height;
+
+        let wrap = |v: f32, size: f32| (v + size / 2.0).rem_euclid(size) - size / 2.0;
+        let actors = std::iter::once(&mut self.player)
+            .chain(self.shots.iter_mut())
+            .chain(self.other_shots.iter_mut())
+            .chain(self.rocks.iter_mut())
+            .chain(self.saucer_shots.iter_mut())
+            .chain(self.pickups.iter_mut());
+        for actor in actors {
+            actor.pos.x = wrap(actor.pos.x, width);
+            actor.pos.y = wrap(actor.pos.y, height);
+        }
+    }
+
+    /// Starts the player over with a fresh set of ships and no score. Other players are left
+    /// alone, they're not ours to reset, and so are the rocks, which we share with them;
+    /// `set_field` starts those over.
+    pub fn reset(&mut self) {
+        self.player = Actor::create_player("self".to_string(), self.ship_class());
+        self.shots = Vec::new();
+        self.saucer = None;
+        self.saucer_shots = Vec::new();
+        self.effects = HashMap::new();
+        self.claims = HashMap::new();
+        self.score = 0;
+        self.kills = 0;
+        self.deaths = 0;
+        self.lives = STARTING_LIVES;
+        self.player_shot_timeout = 0.0;
+        self.saucer_timeout = self.rng.gen_range(SAUCER_MIN_TIME, SAUCER_MAX_TIME);
+        self.keep_player_clear_of_arena();
+    }
+
+    pub fn arena(&self) -> &Arena {
+        &self.arena
+    }
+
+    /// Sets up the stars, obstacles and wormholes. If the player's ship is sitting in harm's way
+    /// it's moved somewhere safer.
+    pub fn set_arena(&mut self, arena: Arena) {
+        self.arena = arena;
+        self.keep_player_clear_of_arena();
+    }
+
+    fn keep_player_clear_of_arena(&mut self) {
+        if self.arena.clearance(self.player.pos, self.width, self.height) < ARENA_CLEARANCE {
+            self.player.pos = self.spawn_point();
+        }
+    }
+
+    /// The class of ship the player is flying.
+    pub fn ship_class(&self) -> ShipClass {
+        match self.player.tag {
+            ActorType::Player(class) => class,
+            _ => ShipClass::default(),
+        }
+    }
+
+    /// Swaps the player's ship for a fresh one of `class`, handling as `flight` says. It takes
+    /// effect straight away, so it's meant for before the player spawns.
+    pub fn set_ship_class(&mut self, class: ShipClass, flight: FlightModel) {
+        let pos = self.player.pos;
+        self.player = Actor::create_player("self".to_string(), class);
+        self.player.pos = pos;
+        self.flight = flight;
+    }
+
+    /// Whether the player has run out of ships.
+    pub fn game_over(&self) -> bool {
+        self.lives <= 0
+    }
+
+    /// Somewhere for a ship to appear: whichever of a handful of spots picked at random is
+    /// furthest from the nearest rock, star or obstacle.
+    fn spawn_point(&mut self) -> Point2 {
+        let mut candidates = vec![Point2::origin()];
+        for _ in 1..RESPAWN_CANDIDATES {
+            let x = self.rng.gen_range(-self.width / 2.0, self.width / 2.0);
+            let y = self.rng.gen_range(-self.height / 2.0, self.height / 2.0);
+            candidates.push(Point2::new(x, y));
+        }
+
+        let (width, height) = (self.width, self.height);
+        let rocks = &self.rocks;
+        let arena = &self.arena;
+        let clearance = |pos: Point2| {
+            rocks
+                .iter()
+                .map(|r| collision::wrapped_delta(pos, r.pos, width, height).norm())
+                .fold(arena.clearance(pos, width, height), f32::min)
+        };
+        candidates
+            .into_iter()
+            .max_by(|&a, &b| clearance(a).total_cmp(&clearance(b)))
+            .unwrap()
+    }
+
+    /// Puts a fresh ship in play after the last one was destroyed, keeping the score and level.
+    /// It goes wherever is safest, and can't be hurt for a few seconds while the player gets their
+    /// bearings.
+    pub fn respawn(&mut self) {
+        let pos = self.spawn_point();
+        let weapon = self.player.weapon;
+        self.player = Actor::create_player("self".to_string(), self.ship_class());
+        self.player.pos = pos;
+        self.player.weapon = weapon;
+        self.player.invulnerable = RESPAWN_INVULNERABILITY;
+        self.shots.clear();
+        self.saucer_shots.clear();
+        self.effects.clear();
+        self.player_shot_timeout = 0.0;
+    }
+
+    /// Advances the simulation by `dt` seconds, with the local player following `input`.
+    pub fn step(&mut self, input: &InputState, dt: f32) -> Vec<Event> {
+        let mut events = Vec::new();
+
+        // Update the player state based on the user input.
+        player_handle_input(&mut self.player, input, &self.flight, dt);
+        self.player_shot_timeout -= dt;
+        if input.fire && self.player_shot_timeout < 0.0 {
+            self.fire_player_shot(&mut events);
+        }
+        if input.hyperspace && self.player.hyperspace_cooldown <= 0.0 {
+            self.hyperspace();
+        }
+        if input.shield && self.player.shield_cooldown <= 0.0 {
+            self.player.shield = SHIELD_TIME;
+            self.player.shield_cooldown = SHIELD_COOLDOWN;
+        }
+
+        // Update the physics for all actors.
+        update_actor_position(&mut self.player, &self.arena, self.width, self.height, dt);
+        apply_drag(&mut self.player, self.flight.drag, dt);
+        clamp_actor_velocity(&mut self.player, self.flight.max_speed);
+        wrap_actor_position(&mut self.player, self.width, self.height);
+        handle_ship_timers(&mut self.player, dt);
+        self.effects.retain(|_, time| {
+            *time -= dt;
+            *time > 0.0
+        });
+        self.claims.retain(|_, (_, age)| {
+            *age += dt;
+            *age < PICKUP_CLAIM_TIME
+        });
+
+        self.step_shared(dt, true, &mut events);
+
+        // Finally we check for our end state. The ship stays dead until someone calls `respawn()`.
+        if !self.player.is_alive() {
+            self.lives -= 1;
+            self.deaths += 1;
+            events.push(Event::PlayerDied {
+                pos: self.player.pos,
+            });
+        }
+
+        events
+    }
+
+    /// Advances everything but the player's ship by `dt` seconds, for while the player is out of
+    /// play. The rocks, saucer and pickups are shared with other peers, so they have to keep
+    /// moving whatever we're doing, or they'd be somewhere else entirely by the time we're back.
+    pub fn step_field(&mut self, dt: f32) -> Vec<Event> {
+        let mut events = Vec::new();
+        self.step_shared(dt, false, &mut events);
+        events
+    }
+
+    /// Moves everything that isn't the player's ship and works out what it's run into. The ship
+    /// only gets in the way when it's `in_play`.
+    fn step_shared(&mut self, dt: f32, in_play: bool, events: &mut Vec<Event>) {
+        for act in &mut self.shots {
+            update_actor_position(act, &self.arena, self.width, self.height, dt);
+            wrap_actor_position(act, self.width, self.height);
+            handle_timed_life(act, dt);
+        }
+
+        for act in &mut self.rocks {
+            update_actor_position(act, &self.arena, self.width, self.height, dt);
+            wrap_actor_position(act, self.width, self.height);
+            handle_timed_life(act, dt);
+        }
+
+        for act in &mut self.other_shots {
+            update_actor_position(act, &self.arena, self.width, self.height, dt);
+            wrap_actor_position(act, self.width, self.height);
+            handle_timed_life(act, dt);
+        }
+
+        for act in &mut self.pickups {
+            update_actor_position(act, &self.arena, self.width, self.height, dt);
+            wrap_actor_position(act, self.width, self.height);
+            handle_timed_life(act, dt);
+        }
+
+        for act in &mut self.saucer_shots {
+            update_actor_position(act, &self.arena, self.width, self.height, dt);
+            wrap_actor_position(act, self.width, self.height);
+            handle_timed_life(act, dt);
+        }
+
+        self.update_saucer(dt, in_play, events);
+
+        // Handle the results of things moving:
+        //
+        // bouncing things off each other, collision detection, object death, and if we have killed
+        // all the rocks in the level, spawn more of them.
+        if self.bounce {
+            self.handle_bounces(in_play);
+        }
+        self.handle_arena(in_play);
+        self.handle_collisions(in_play, events);
+        self.handle_saucer_collisions(in_play, events);
+        if in_play {
+            self.handle_pickups(events);
+        }
+        self.clear_dead_stuff();
+        self.check_for_level_respawn(events);
+    }
+
+    /// The weapon the player has picked, with whatever power-ups they have running applied.
+    pub fn weapon(&self) -> Weapon {
+        let mut weapon = WEAPONS[self.player.weapon];
+        if self.effects.contains_key(&PickupKind::RapidFire) {
+            weapon.fire_time /= 2.0;
+        }
+        if self.effects.contains_key(&PickupKind::Spread) {
+            weapon.projectiles += 2;
+            weapon.spread += 2.0 * PICKUP_SPREAD_ANGLE;
+        }
+        if self.effects.contains_key(&PickupKind::Piercing) {
+            weapon.pierce += PICKUP_PIERCE;
+        }
+        weapon
+    }
+
+    /// Switches to the weapon with the given index into `WEAPONS`, if there is one.
+    pub fn select_weapon(&mut self, index: usize) {
+        if index < WEAPONS.len() {
+            self.player.weapon = index;
+        }
+    }
+
+    fn fire_player_shot(&mut self, events: &mut Vec<Event>) {
+        let weapon = self.weapon();
+        self.player_shot_timeout = weapon.fire_time;
+
+        let player = &self.player;
+        let volley = weapon.volley("self", player.pos, player.facing, player.velocity);
+        self.shots.extend(volley);
+
+        events.push(Event::ShotFired {
+            pos: player.pos,
+            facing: player.facing,
+            velocity: player.velocity,
+            weapon,
+        });
+    }
+
+    /// Adds a volley that another player fired, so we can see it.
+    pub fn add_other_volley(
+        &mut self,
+        owner: &str,
+        pos: Point2,
+        facing: f32,
+        velocity: Vector2,
+        weapon: &Weapon,
+    ) {
+        let volley = weapon.volley(owner, pos, facing, velocity);
+        self.other_shots.extend(volley);
+    }
+
+    /// Jumps the ship to a random spot, where it comes out at a standstill. Every so often the jump
+    /// goes wrong and the ship doesn't come out at all.
+    fn hyperspace(&mut self) {
+        self.player.hyperspace_cooldown = HYPERSPACE_COOLDOWN;
+        if self.rng.gen_bool(HYPERSPACE_FAILURE_CHANCE) {
+            self.player.health = 0.0;
+            return;
+        }
+
+        let x = self.rng.gen_range(-self.width / 2.0, self.width / 2.0);
+        let y = self.rng.gen_range(-self.height / 2.0, self.height / 2.0);
+        self.player.pos = Point2::new(x, y);
+        self.player.velocity = na::zero();
+    }
+
+    /// Sends out a saucer when it's time for one, flies it across the screen and has it take pot
+    /// shots at whoever's about.
+    fn update_saucer(&mut self, dt: f32, in_play: bool, events: &mut Vec<Event>) {
+        if self.simulate_saucers && self.saucer.is_none() {
+            self.saucer_timeout -= dt;
+            if self.saucer_timeout < 0.0 {
+                self.spawn_saucer();
+            }
+        }
+
+        let saucer = match &mut self.saucer {
+            Some(saucer) => saucer,
+            None => return,
+        };
+        // Saucers fly high above the arena, so nothing in it gets in their way.
+        update_actor_position(saucer, &Arena::default(), self.width, self.height, dt);
+        handle_timed_life(saucer, dt);
+
+        // Saucers fly across the screen once rather than wrapping round.
+        if saucer.pos.x.abs() > self.width / 2.0 {
+            self.saucer = None;
+            self.saucer_timeout = self.rng.gen_range(SAUCER_MIN_TIME, SAUCER_MAX_TIME);
+            return;
+        }
+        wrap_actor_position(saucer, self.width, self.height);
+
+        if self.simulate_saucers {
+            self.saucer_shot_timeout -= dt;
+            if self.saucer_shot_timeout < 0.0 {
+                self.fire_saucer_shot(in_play, events);
+            }
+        }
+    }
+
+    /// Sends a saucer in from the left or right edge. Small saucers turn up more and more often
+    /// the further into the game we get.
+    fn spawn_saucer(&mut self) {
+        let small_chance = (0.2 + 0.15 * f64::from(self.level)).min(0.9);
+        let size = if self.rng.gen_bool(small_chance) {
+            SaucerSize::Small
+        } else {
+            SaucerSize::Large
+        };
+
+        let mut saucer = Actor::create_saucer("self".to_string(), size, self.rng.gen());
+        let direction = if self.rng.gen() { 1.0 } else { -1.0 };
+        let y = self.rng.gen_range(-self.height / 2.0, self.height / 2.0);
+        saucer.pos = Point2::new(-direction * self.width / 2.0, y);
+        saucer.velocity = Vector2::new(direction * SAUCER_SPEED, 0.0);
+
+        self.saucer = Some(saucer);
+        self.saucer_shot_timeout = SAUCER_SHOT_TIME;
+    }
+
+    /// Large saucers fire any which way. Small ones aim at the nearest ship, more accurately the
+    /// higher the level, leaving ours alone unless it's `in_play`. Either way the saucer wanders
+    /// up or down a bit after every shot.
+    fn fire_saucer_shot(&mut self, in_play: bool, events: &mut Vec<Event>) {
+        let (pos, tag) = match &self.saucer {
+            Some(saucer) => (saucer.pos, saucer.tag),
+            None => return,
+        };
+        self.saucer_shot_timeout = SAUCER_SHOT_TIME;
+
+        let (width, height) = (self.width, self.height);
+        let target = std::iter::once(&self.player)
+            .filter(|p| in_play && p.is_alive())
+            .chain(self.other_players.values())
+            .map(|p| collision::wrapped_delta(pos, p.pos, width, height))
+            .min_by(|a, b| a.norm().total_cmp(&b.norm()));
+
+        let angle = match (tag, target) {
+            (ActorType::Saucer(SaucerSize::Small), Some(target)) => {
+                let spread = (0.5 - 0.1 * self.level as f32).max(0.05);
+                target.x.atan2(target.y) + self.rng.gen_range(-spread, spread)
+            }
+            _ => self.rng.gen::<f32>() * 2.0 * std::f32::consts::PI,
+        };
+
+        let mut shot = Actor::create_shot("saucer".to_string());
+        shot.pos = pos;
+        shot.facing = angle;
+        shot.velocity = vec_from_angle(angle) * SAUCER_SHOT_SPEED;
+        events.push(Event::SaucerFired {
+            pos,
+            velocity: shot.velocity,
+        });
+        self.saucer_shots.push(shot);
+
+        let wander = self.rng.gen_range(-1, 2) as f32 * SAUCER_SPEED / 2.0;
+        if let Some(saucer) = &mut self.saucer {
+            saucer.velocity.y = wander;
+        }
+    }
+
+    /// Adds a shot fired by a saucer somebody else is flying.
+    pub fn add_saucer_shot(&mut self, pos: Point2, velocity: Vector2) {
+        let mut shot = Actor::create_shot("saucer".to_string());
+        shot.pos = pos;
+        shot.facing = velocity.x.atan2(velocity.y);
+        shot.velocity = velocity;
+        self.saucer_shots.push(shot);
+    }
+
+    /// Takes the latest word on the saucer from whoever is flying it.
+    pub fn sync_saucer(&mut self, saucer: Actor) {
+        if self.last_destroyed_saucer != Some(saucer.id) {
+            self.saucer = Some(saucer);
+        }
+    }
+
+    /// Damages the saucer with the given id because somebody else shot it. Nobody here gets any
+    /// points if that finishes it off.
+    pub fn damage_saucer(&mut self, id: u32, damage: f32) -> Option<Event> {
+        let saucer = match &mut self.saucer {
+            Some(saucer) if saucer.id == id => saucer,
+            _ => return None,
+        };
+        saucer.take_damage(damage);
+        if saucer.is_alive() {
+            return None;
+        }
+
+        let event = Event::SaucerDestroyed {
+            id,
+            pos: saucer.pos,
+        };
+        self.clear_dead_stuff();
+        Some(event)
+    }
+
+    fn clear_dead_stuff(&mut self) {
+        self.shots.retain(|s| s.is_alive());
+        self.other_shots.retain(|s| s.is_alive());
+        self.rocks.retain(|r| r.is_alive());
+        self.saucer_shots.retain(|s| s.is_alive());
+        self.pickups.retain(|p| p.is_alive());
+
+        if let Some(saucer) = &self.saucer {
+            if !saucer.is_alive() {
+                self.last_destroyed_saucer = Some(saucer.id);
+                self.saucer = None;
+                self.saucer_timeout = self.rng.gen_range(SAUCER_MIN_TIME, SAUCER_MAX_TIME);
+            }
+        }
+    }
+
+    /// Whether the player's ship is safe from harm just now.
+    fn player_is_protected(&self) -> bool {
+        self.player.invulnerable > 0.0 || self.player.hit_grace > 0.0 || self.player.shield > 0.0
+    }
+
+    /// Stars burn up whatever flies into them, unless it's a ship that can't be hurt just now.
+    /// Obstacles turn ships, rocks and pickups away, and stop shots dead. Our ship is left out
+    /// unless it's `in_play`.
+    fn handle_arena(&mut self, in_play: bool) {
+        let protected = self.player_is_protected();
+        let arena = &self.arena;
+        let (width, height) = (self.width, self.height);
+
+        if in_play {
+            if !protected
+                && arena
+                    .star_at(self.player.pos, self.player.bbox_size, width, height)
+                    .is_some()
+            {
+                let health = self.player.health;
+                hurt_ship(&mut self.player, health);
+            }
+            bounce_off_obstacles(&mut self.player, arena, width, height);
+        }
+
+        for act in self.rocks.iter_mut().chain(self.pickups.iter_mut()) {
+            if arena.star_at(act.pos, act.bbox_size, width, height).is_some() {
+                act.health = 0.0;
+            }
+            bounce_off_obstacles(act, arena, width, height);
+        }
+
+        let shots = self
+            .shots
+            .iter_mut()
+            .chain(self.other_shots.iter_mut())
+            .chain(self.saucer_shots.iter_mut());
+        for shot in shots {
+            if arena.star_at(shot.pos, shot.bbox_size, width, height).is_some()
+                || arena
+                    .obstacle_push(shot.pos, shot.bbox_size, width, height)
+                    .is_some()
+            {
+                shot.lifetime = 0.0;
+            }
+        }
+    }
+
+    /// Bounces rocks off each other, and our ship off other ships if it's `in_play`.
+    ///
+    /// Rock pairs are always resolved lowest id first, so peers sharing rocks bounce them the
+    /// same way. Other players' ships belong to them: we only bounce our own ship, and they'll
+    /// work out the other half of the very same bounce on their side.
+    fn handle_bounces(&mut self, in_play: bool) {
+        let mut grid = SpatialGrid::new(self.width, self.height, GRID_CELL_SIZE);
+        for (i, rock) in self.rocks.iter().enumerate() {
+            grid.insert(i, rock);
+        }
+
+        let mut order: Vec<usize> = (0..self.rocks.len()).collect();
+        order.sort_by_key(|&i| self.rocks[i].id);
+
+        for i in order {
+            for j in grid.query(&self.rocks[i]) {
+                let (a, b) = (&self.rocks[i], &self.rocks[j]);
+                if b.id <= a.id || !collision::actors_collide(a, b, self.width, self.height) {
+                    continue;
+                }
+
+                if let Some((dv_a, dv_b)) = collision::bounce(a, b, self.width, self.height) {
+                    self.rocks[i].velocity += dv_a;
+                    self.rocks[j].velocity += dv_b;
+                }
+            }
+        }
+
+        if !in_play {
+            return;
+        }
+        for other in self.other_players.values() {
+            if !collision::actors_collide(&self.player, other, self.width, self.height) {
+                continue;
+            }
+
+            if let Some((dv, _)) = collision::bounce(&self.player, other, self.width, self.height) {
+                self.player.velocity += dv;
+            }
+        }
+    }
+
+    /// Rocks hurt our ship if it's `in_play`, and our shots break rocks up whether it is or not.
+    fn handle_collisions(&mut self, in_play: bool, events: &mut Vec<Event>) {
+        let mut grid = SpatialGrid::new(self.width, self.height, GRID_CELL_SIZE);
+        for (i, rock) in self.rocks.iter().enumerate() {
+            grid.insert(i, rock);
+        }
+
+        if in_play && !self.player_is_protected() {
+            for i in grid.query(&self.player) {
+                let rock = &self.rocks[i];
+                if !collision::actors_collide(&self.player, rock, self.width, self.height) {
+                    continue;
+                }
+                if let ActorType::Rock(size) = rock.tag {
+                    hurt_ship(&mut self.player, size.damage());
+                    break;
+                }
+            }
+        }
+
+        let mut pieces = Vec::new();
+        let mut drops = Vec::new();
+        for shot in &mut self.shots {
+            for i in grid.query(shot) {
+                let rock = &mut self.rocks[i];
+                if !shot.is_alive() || !rock.is_alive() || shot.hits.contains(&rock.id) {
+                    continue;
+                }
+
+                if collision::actors_collide(shot, rock, self.width, self.height) {
+                    if shot.pierce > 0 {
+                        shot.pierce -= 1;
+                    } else {
+                        shot.health = 0.0;
+                    }
+                    shot.hits.push(rock.id);
+                    rock.take_damage(shot.damage);
+                    events.push(Event::RockHit {
+                        id: rock.id,
+                        damage: shot.damage,
+                    });
+                    if rock.is_alive() {
+                        continue;
+                    }
+
+                    if let ActorType::Rock(size) = rock.tag {
+                        self.score += size.points();
+                    }
+                    self.kills += 1;
+                    // A shot going on through would otherwise hit the pieces straight away.
+                    let split = split_rock(rock);
+                    shot.hits.extend(split.iter().map(|piece| piece.id));
+                    pieces.extend(split);
+                    drops.extend(rock_pickup(rock));
+
+                    events.push(Event::RockDestroyed {
+                        id: rock.id,
+                        pos: rock.pos,
+                    });
+                }
+            }
+        }
+        self.rocks.extend(pieces);
+        self.pickups.extend(drops);
+    }
+
+    /// Gives the player whatever pickups they fly into, and tells everyone else to take them away.
+    /// If two players reach a pickup at once they both claim it, and the one with the lowest id
+    /// keeps it while the other hands it back with `lose_pickup`.
+    fn handle_pickups(&mut self, events: &mut Vec<Event>) {
+        if !self.player.is_alive() {
+            return;
+        }
+
+        for pickup in &mut self.pickups {
+            if !collision::actors_collide(&self.player, pickup, self.width, self.height) {
+                continue;
+            }
+            let kind = match pickup.tag {
+                ActorType::Pickup(kind) => kind,
+                _ => continue,
+            };
+
+            pickup.health = 0.0;
+            match kind {
+                PickupKind::ExtraLife => self.lives += 1,
+                PickupKind::Shield => self.player.shield = SHIELD_TIME,
+                _ => {
+                    self.effects.insert(kind, PICKUP_EFFECT_TIME);
+                }
+            }
+            self.claims.insert(pickup.id, (kind, 0.0));
+            events.push(Event::PickupClaimed {
+                id: pickup.id,
+                kind,
+            });
+        }
+    }
+
+    /// Undoes a pickup we took, because somebody else with a better claim got there at the same
+    /// moment. Pickups we haven't taken lately are left alone.
+    pub fn lose_pickup(&mut self, id: u32) {
+        let kind = match self.claims.remove(&id) {
+            Some((kind, _)) => kind,
+            None => return,
+        };
+        match kind {
+            PickupKind::ExtraLife => self.lives -= 1,
+            PickupKind::Shield => self.player.shield = 0.0,
+            _ => {
+                self.effects.remove(&kind);
+            }
+        }
+    }
+
+    /// Takes away a pickup that somebody else got to first.
+    pub fn remove_pickup(&mut self, id: u32) {
+        self.pickups.retain(|p| p.id != id);
+    }
+
+    /// Our shots damage the saucer, and the saucer's shots damage our ship if it's `in_play`.
+    /// Flying into the saucer takes it down with you, and still scores.
+    fn handle_saucer_collisions(&mut self, in_play: bool, events: &mut Vec<Event>) {
+        let (width, height) = (self.width, self.height);
+
+        if in_play && !self.player_is_protected() {
+            for shot in &mut self.saucer_shots {
+                if collision::actors_collide(&self.player, shot, width, height) {
+                    shot.health = 0.0;
+                    hurt_ship(&mut self.player, shot.damage);
+                    break;
+                }
+            }
+        }
+
+        let exposed = in_play && !self.player_is_protected();
+        let saucer = match &mut self.saucer {
+            Some(saucer) => saucer,
+            None => return,
+        };
+
+        let shot_down = self.shots.iter_mut().find(|shot| {
+            shot.is_alive()
+                && !shot.hits.contains(&saucer.id)
+                && collision::actors_collide(shot, saucer, width, height)
+        });
+        let rammed = exposed && collision::actors_collide(&self.player, saucer, width, height);
+
+        let damage = if let Some(shot) = shot_down {
+            if shot.pierce > 0 {
+                shot.pierce -= 1;
+            } else {
+                shot.health = 0.0;
+            }
+            shot.hits.push(saucer.id);
+            shot.damage
+        } else if rammed {
+            self.player.health = 0.0;
+            saucer.health
+        } else {
+            return;
+        };
+
+        saucer.take_damage(damage);
+        events.push(Event::SaucerHit {
+            id: saucer.id,
+            damage,
+        });
+        if saucer.is_alive() {
+            return;
+        }
+
+        if let ActorType::Saucer(size) = saucer.tag {
+            self.score += size.points();
+        }
+        self.kills += 1;
+        events.push(Event::SaucerDestroyed {
+            id: saucer.id,
+            pos: saucer.pos,
+        });
+    }
+
+    /// Takes the latest word on a rock from whoever's running the field. If we already have it,
+    /// it's moved to wherever they have it; otherwise it's added.
+    pub fn add_rock(&mut self, rock: Actor) {
+        match self.rocks.iter_mut().find(|r| r.id == rock.id) {
+            Some(ours) => {
+                ours.pos = rock.pos;
+                ours.velocity = rock.velocity;
+                ours.health = rock.health;
+            }
+            None => self.rocks.push(rock),
+        }
+    }
+
+    /// Damages the rock with the given id because somebody else shot it, splitting it up just like
+    /// we would have if that finishes it off. Nobody here gets any points for it.
+    pub fn damage_rock(&mut self, id: u32, damage: f32) -> Option<Event> {
+        let rock = self.rocks.iter_mut().find(|r| r.id == id && r.is_alive())?;
+        rock.take_damage(damage);
+        if rock.is_alive() {
+            return None;
+        }
+
+        let event = Event::RockDestroyed {
+            id: rock.id,
+            pos: rock.pos,
+        };
+
+        let pieces = split_rock(rock);
+        self.pickups.extend(rock_pickup(rock));
+        self.rocks.extend(pieces);
+        self.clear_dead_stuff();
+
+        Some(event)
+    }
+
+    /// Moves on to the next level once every rock is gone. The new rocks turn up around the
+    /// middle of the world wherever the ship happens to be, so it gets a moment's grace to get
+    /// out of their way.
+    fn check_for_level_respawn(&mut self, events: &mut Vec<Event>) {
+        if self.advance_levels && self.rocks.is_empty() {
+            self.level += 1;
+            let r = level_rocks(self.seed, self.level);
+            events.extend(r.iter().map(|rock| Event::RockSpawned { id: rock.id }));
+            self.rocks.extend(r);
+            self.player.invulnerable = self.player.invulnerable.max(RESPAWN_INVULNERABILITY);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weapon::{MAX_PROJECTILES, MAX_SPREAD};
+
+    /// A classic-sized world with nothing in it, and no rocks on the way.
+    fn empty_world() -> World {
+        let mut world = World::new(800.0, 600.0, 0);
+        world.advance_levels = false;
+        world.rocks.clear();
+        world
+    }
+
+    /// An empty world but for one large rock sitting still at `pos`.
+    fn world_with_rock(pos: Point2) -> World {
+        let mut world = empty_world();
+        let mut rock = Actor::create_rock("self".to_string(), RockSize::Large, 0);
+        rock.pos = pos;
+        world.rocks.push(rock);
+        world
+    }
+
+    /// Steps the world `ticks` times at 60 a second with the same input, and collects everything
+    /// that happened.
+    fn run(world: &mut World, input: &InputState, ticks: u32) -> Vec<Event> {
+        (0..ticks)
+            .flat_map(|_| world.step(input, 1.0 / 60.0))
+            .collect()
+    }
+
+    fn firing() -> InputState {
+        InputState {
+            fire: true,
+            ..InputState::default()
+        }
+    }
+
+    #[test]
+    fn test_firing() {
+        let mut world = world_with_rock(Point2::new(300.0, 0.0));
+        let events = run(&mut world, &firing(), 1);
+
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            events[0],
+            Event::ShotFired { pos, .. } if pos == Point2::origin()
+        ));
+        assert_eq!(world.shots.len(), 1);
+    }
+
+    #[test]
+    fn test_rock_kills_player() {
+        let mut world = world_with_rock(Point2::origin());
+        let events = run(&mut world, &InputState::default(), 1);
+
+        assert!(events.contains(&Event::PlayerDied {
+            pos: world.player.pos
+        }));
+        assert_eq!(world.deaths, 1);
+    }
+
+    #[test]
+    fn test_seeded_worlds_match() {
+        let a = World::new(800.0, 600.0, 1234);
+        let b = World::new(800.0, 600.0, 1234);
+
+        let positions = |world: &World| world.rocks.iter().map(|r| r.pos).collect::<Vec<_>>();
+        assert_eq!(positions(&a), positions(&b));
+    }
+
+    #[test]
+    fn test_levels_match() {
+        let mut a = World::new(800.0, 600.0, 1234);
+        let mut b = World::new(800.0, 600.0, 1234);
+
+        // Only one of them has been rolling dice for other things in the meantime.
+        a.respawn();
+        a.hyperspace();
+        a.spawn_saucer();
+
+        a.rocks.clear();
+        b.rocks.clear();
+        run(&mut a, &InputState::default(), 1);
+        run(&mut b, &InputState::default(), 1);
+        let rocks = |world: &World| {
+            world
+                .rocks
+                .iter()
+                .map(|r| (r.id, r.pos))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(a.level, 1);
+        assert_eq!(rocks(&a), rocks(&b));
+    }
+
+    #[test]
+    fn test_spin_is_independent_of_tick_rate() {
+        let spin = |ticks: u32| {
+            let mut shot = Actor::create_shot("self".to_string());
+            for _ in 0..ticks {
+                update_actor_position(
+                    &mut shot,
+                    &Arena::default(),
+                    800.0,
+                    600.0,
+                    1.0 / ticks as f32,
+                );
+            }
+            shot.facing
+        };
+
+        assert!((spin(30) - spin(120)).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_rocks_split() {
+        let mut a = World::new(800.0, 600.0, 99);
+        let mut b = World::new(800.0, 600.0, 99);
+        let id = a.rocks[0].id;
+
+        a.damage_rock(id, 1.0).unwrap();
+        b.damage_rock(id, 1.0).unwrap();
+
+        let mediums: Vec<_> = a
+            .rocks
+            .iter()
+            .filter(|r| r.tag == ActorType::Rock(RockSize::Medium))
+            .collect();
+        assert!(mediums.len() == 2 || mediums.len() == 3);
+
+        let ids = |world: &World| world.rocks.iter().map(|r| r.id).collect::<Vec<_>>();
+        assert_eq!(ids(&a), ids(&b));
+        assert!(a.damage_rock(id, 1.0).is_none());
+    }
+
+    #[test]
+    fn test_outline_doesnt_give_away_split() {
+        // However many points a rock's outline has, it's as likely to break into two pieces as
+        // three.
+        let rocks: Vec<Actor> = (0..1000)
+            .map(|id| Actor::create_rock("self".to_string(), RockSize::Large, id))
+            .collect();
+        for points in 8..13 {
+            let (threes, total) = rocks
+                .iter()
+                .filter(|r| r.polygon().len() == points)
+                .fold((0, 0), |(threes, total), r| {
+                    (threes + (split_rock(r).len() == 3) as u32, total + 1)
+                });
+            let share = threes as f32 / total as f32;
+            assert!(share > 0.3 && share < 0.7, "{} points: {}", points, share);
+        }
+    }
+
+    #[test]
+    fn test_respawn() {
+        let mut world = World::new(800.0, 600.0, 5);
+        world.score = 120;
+        world.rocks[0].pos = world.player.pos;
+        run(&mut world, &InputState::default(), 1);
+        assert_eq!(world.lives, STARTING_LIVES - 1);
+
+        world.respawn();
+        assert_eq!(world.score, 120);
+        assert!(world.player.invulnerable > 0.0);
+        for rock in &world.rocks {
+            let delta = collision::wrapped_delta(world.player.pos, rock.pos, 800.0, 600.0);
+            assert!(delta.norm() > 50.0);
+        }
+
+        // A rock right on top of a ship that's just respawned passes straight through it.
+        world.rocks[0].pos = world.player.pos;
+        let events = run(&mut world, &InputState::default(), 1);
+        assert!(events
+            .iter()
+            .all(|e| !matches!(e, Event::PlayerDied { .. })));
+    }
+
+    #[test]
+    fn test_shooting_saucer() {
+        let mut world = world_with_rock(Point2::new(300.0, -200.0));
+        world.simulate_saucers = false;
+
+        let mut saucer = Actor::create_saucer("self".to_string(), SaucerSize::Large, 3);
+        saucer.pos = Point2::new(0.0, 100.0);
+        world.saucer = Some(saucer);
+        let score = world.score;
+
+        let events = run(&mut world, &firing(), 60);
+
+        assert!(events.contains(&Event::SaucerDestroyed {
+            id: 3,
+            pos: Point2::new(0.0, 100.0)
+        }));
+        assert!(world.saucer.is_none());
+        assert!(world.score >= score + SaucerSize::Large.points());
+        assert!(world.kills >= 1);
+    }
+
+    #[test]
+    fn test_pickups() {
+        let extra_life = (0..1000)
+            .map(|id| Actor::create_rock("self".to_string(), RockSize::Small, id))
+            .filter_map(|rock| rock_pickup(&rock))
+            .find(|p| p.tag == ActorType::Pickup(PickupKind::ExtraLife))
+            .expect("no rock dropped an extra life");
+
+        let rock = Actor::create_rock("self".to_string(), RockSize::Small, extra_life.id);
+        assert_eq!(rock_pickup(&rock).map(|p| p.tag), Some(extra_life.tag));
+
+        let mut world = empty_world();
+        world.pickups.push(extra_life);
+        let events = run(&mut world, &InputState::default(), 1);
+
+        assert_eq!(world.lives, STARTING_LIVES + 1);
+        assert!(world.pickups.is_empty());
+        assert!(events.contains(&Event::PickupClaimed {
+            id: rock.id,
+            kind: PickupKind::ExtraLife
+        }));
+
+        // Somebody else got there at the same time and won it, so it's handed back, but only
+        // the once.
+        world.lose_pickup(rock.id);
+        world.lose_pickup(rock.id);
+        assert_eq!(world.lives, STARTING_LIVES);
+    }
+
+    #[test]
+    fn test_spread_volleys_fit_the_limits() {
+        let mut world = empty_world();
+        world.effects.insert(PickupKind::Spread, PICKUP_EFFECT_TIME);
+        for index in 0..WEAPONS.len() {
+            world.select_weapon(index);
+            let weapon = world.weapon();
+            assert!(weapon.projectiles <= MAX_PROJECTILES);
+            assert!(weapon.spread <= MAX_SPREAD);
+        }
+    }
+
+    #[test]
+    fn test_damage() {
+        assert_eq!(rock_health(0), 1.0);
+        assert_eq!(rock_health(3), 2.0);
+
+        let mut world = world_with_rock(Point2::new(0.0, 60.0));
+        world.rocks[0].health = 2.0;
+        let id = world.rocks[0].id;
+
+        // The blaster takes two hits to break up a tough rock, and every hit gets reported.
+        let events = run(&mut world, &firing(), 90);
+        let hits = events
+            .iter()
+            .filter(|e| matches!(e, Event::RockHit { id: hit, .. } if *hit == id))
+            .count();
+        assert_eq!(hits, 2);
+        assert!(world.rocks.iter().all(|r| r.id != id));
+
+        // A small rock dents a ship without destroying it.
+        let mut world = empty_world();
+        world
+            .rocks
+            .push(Actor::create_rock("self".to_string(), RockSize::Small, 1));
+        run(&mut world, &InputState::default(), 1);
+        assert_eq!(world.player.health, ShipClass::Balanced.health() - 1.0);
+        assert!(world.player.flash > 0.0);
+
+        // It gets a moment's grace, without blinking as if it had just respawned.
+        assert!(world.player.hit_grace > 0.0);
+        assert_eq!(world.player.invulnerable, 0.0);
+    }
+
+    #[test]
+    fn test_piercing_hits_once() {
+        let mut world = world_with_rock(Point2::new(0.0, 60.0));
+        world.rocks[0].health = 10.0;
+        world.select_weapon(3);
+
+        // The lance goes right through the rock, but only knocks it once on the way.
+        let mut events = run(&mut world, &firing(), 1);
+        events.extend(run(&mut world, &InputState::default(), 30));
+        let hits = events
+            .iter()
+            .filter(|e| matches!(e, Event::RockHit { .. }))
+            .count();
+        assert_eq!(hits, 1);
+        assert_eq!(world.rocks[0].health, 7.0);
+    }
+
+    #[test]
+    fn test_flight_model() {
+        let mut world = empty_world();
+        world.player.velocity = Vector2::new(100.0, 0.0);
+        world.flight.drag = 0.5;
+
+        // Drag bleeds off speed when the engines are off...
+        run(&mut world, &InputState::default(), 60);
+        assert!(world.player.velocity.x < 70.0);
+
+        // ...and turning winds up rather than snapping to full speed.
+        let input = InputState {
+            xaxis: 1.0,
+            ..InputState::default()
+        };
+        run(&mut world, &input, 1);
+        assert!(world.player.ang_vel > 0.0 && world.player.ang_vel < world.flight.turn_rate);
+    }
+
+    #[test]
+    fn test_ship_class() {
+        let mut world = empty_world();
+        world.set_ship_class(ShipClass::Gunship, ShipClass::Gunship.flight());
+        assert_eq!(world.player.tag, ActorType::Player(ShipClass::Gunship));
+        assert_eq!(world.player.health, ShipClass::Gunship.health());
+        assert_eq!(world.weapon().name, "Scatter");
+
+        // The class sticks through losing a ship and starting over.
+        world.respawn();
+        assert_eq!(world.ship_class(), ShipClass::Gunship);
+        world.reset();
+        assert_eq!(world.ship_class(), ShipClass::Gunship);
+    }
+
+    #[test]
+    fn test_shared_field() {
+        let mut a = World::new(800.0, 600.0, 1);
+        let mut b = World::new(800.0, 600.0, 2);
+        b.advance_levels = false;
+
+        // Starting over leaves the rocks everyone shares alone.
+        a.rocks.truncate(1);
+        a.reset();
+        assert_eq!(a.rocks.len(), 1);
+
+        // Only the peer running the field moves on when the rocks run out...
+        a.rocks.clear();
+        b.rocks.clear();
+        run(&mut a, &InputState::default(), 1);
+        run(&mut b, &InputState::default(), 1);
+        assert!(b.rocks.is_empty());
+
+        // ...and the others fall in with it, taking its rocks as they are rather than as they
+        // were when the level started.
+        a.rocks[0].pos = Point2::new(123.0, 45.0);
+        b.rocks = level_rocks(b.seed(), b.level);
+        b.follow_field(a.seed(), a.level);
+        assert!(b.rocks.is_empty());
+        for rock in &a.rocks {
+            let mut copy = Actor::create_rock("a".to_string(), RockSize::Large, rock.id);
+            copy.pos = rock.pos;
+            b.add_rock(copy);
+        }
+        let ids = |world: &World| world.rocks.iter().map(|r| r.id).collect::<Vec<_>>();
+        assert_eq!(ids(&a), ids(&b));
+        assert_eq!(b.rocks[0].pos, Point2::new(123.0, 45.0));
+
+        // Later news of a rock we have moves it.
+        let mut moved = Actor::create_rock("a".to_string(), RockSize::Large, a.rocks[1].id);
+        moved.pos = Point2::new(-10.0, 10.0);
+        b.add_rock(moved);
+        assert_eq!(b.rocks.len(), a.rocks.len());
+        assert_eq!(b.rocks[1].pos, Point2::new(-10.0, 10.0));
+    }
+
+    #[test]
+    fn test_set_size() {
+        let mut world = world_with_rock(Point2::new(700.0, -500.0));
+        world.set_size(1600.0, 1200.0);
+        assert_eq!(world.rocks[0].pos, Point2::new(700.0, -500.0));
+
+        world.set_size(800.0, 600.0);
+        assert_eq!((world.width, world.height), (800.0, 600.0));
+        assert_eq!(world.rocks[0].pos, Point2::new(-100.0, 100.0));
+    }
+
+    #[test]
+    fn test_field_carries_on_without_the_player() {
+        let mut world = world_with_rock(Point2::new(0.0, 0.0));
+        world.rocks[0].velocity = Vector2::new(10.0, 0.0);
+        let start = world.player.pos;
+
+        // The rock moves on, and goes straight through a ship that isn't in play.
+        let events: Vec<Event> = (0..60).flat_map(|_| world.step_field(1.0 / 60.0)).collect();
+        assert!(world.rocks[0].pos.x > 9.0);
+        assert_eq!(world.player.pos, start);
+        assert!(world.player.is_alive());
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn test_arena() {
+        let mut world = empty_world();
+        world.set_arena(Arena::parse("star 0 0 20 50\nobstacle 200 0 20").unwrap());
+
+        // The ship was moved off the star it would have started on.
+        assert!(world.arena().clearance(world.player.pos, 800.0, 600.0) >= ARENA_CLEARANCE);
+
+        // Stars pull...
+        world.player.pos = Point2::new(0.0, 100.0);
+        world.player.velocity = Vector2::new(0.0, 0.0);
+        run(&mut world, &InputState::default(), 1);
+        assert!(world.player.velocity.y < 0.0);
+
+        // ...obstacles push back...
+        world.player.pos = Point2::new(180.0, 0.0);
+        world.player.velocity = Vector2::new(50.0, 0.0);
+        run(&mut world, &InputState::default(), 1);
+        assert!(world.player.velocity.x < 0.0);
+
+        // ...and flying into a star is the end of the ship.
+        world.player.pos = Point2::new(0.0, 25.0);
+        let events = run(&mut world, &InputState::default(), 1);
+        assert!(events.iter().any(|e| matches!(e, Event::PlayerDied { .. })));
+    }
+
+    #[test]
+    fn test_shield() {
+        let mut world = world_with_rock(Point2::origin());
+        let input = InputState {
+            shield: true,
+            ..InputState::default()
+        };
+        let events = run(&mut world, &input, 1);
+        assert!(events.is_empty());
+        assert!(world.player.shield > 0.0);
+
+        // It can't be raised again straight after it runs out.
+        world.player.shield = 0.0;
+        world.rocks[0].pos = world.player.pos;
+        let events = run(&mut world, &input, 1);
+        assert!(events.contains(&Event::PlayerDied {
+            pos: world.player.pos
+        }));
+    }
+}